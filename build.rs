@@ -17,15 +17,30 @@ fn main() {
 
     let kernel = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_KERNEL_WITH_BOOTLOADER").unwrap());
     
+    // an optional PSF font (uncompressed, e.g. gunzip a font of /usr/share/consolefonts) is passed
+    // to the kernel as ramdisk. It uses it for the chars its built-in font does not have
+    println!("cargo:rerun-if-env-changed=KERNEL_FONT");
+    let font = std::env::var_os("KERNEL_FONT").map(PathBuf::from);
+
     // create an UEFI disk image (optional)
     let uefi_path = out_dir.join("uefi.img");
-    bootloader::UefiBoot::new(&kernel).create_disk_image(&uefi_path).unwrap();
+    let mut uefi_boot = bootloader::UefiBoot::new(&kernel);
+    if let Some(font) = &font {
+        uefi_boot.set_ramdisk(font);
+    }
+    uefi_boot.create_disk_image(&uefi_path).unwrap();
 
     // create a BIOS disk image
     let bios_path = out_dir.join("bios.img");
-    bootloader::BiosBoot::new(&kernel).create_disk_image(&bios_path).unwrap();
+    let mut bios_boot = bootloader::BiosBoot::new(&kernel);
+    if let Some(font) = &font {
+        bios_boot.set_ramdisk(font);
+    }
+    bios_boot.create_disk_image(&bios_path).unwrap();
 
     // pass the disk image paths as env variables to the main.rs
     println!("cargo:rustc-env=UEFI_PATH={}", uefi_path.display());
     println!("cargo:rustc-env=BIOS_PATH={}", bios_path.display());
+    // the kernel ELF, so that the runner can point gdb to the symbols in --debug mode
+    println!("cargo:rustc-env=KERNEL_PATH={}", kernel.display());
 }
\ No newline at end of file
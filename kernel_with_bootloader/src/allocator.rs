@@ -0,0 +1,207 @@
+//Kernel heap allocators. The one used as #[global_allocator] is chosen through cargo features:
+//  bump_allocator, linked_list_allocator or fixed_size_block_allocator (default)
+//e.g. cargo build --no-default-features --features linked_list_allocator
+//Ref: https://os.phil-opp.com/allocator-designs/
+
+#[cfg(feature = "bump_allocator")]
+pub mod bump;
+#[cfg(feature = "fixed_size_block_allocator")]
+pub mod fixed_size_block;
+#[cfg(any(feature = "linked_list_allocator", feature = "fixed_size_block_allocator"))]
+pub mod linked_list; //also the fallback of the fixed size block allocator
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+
+use crate::println;
+
+#[cfg(any(
+    all(feature = "bump_allocator", feature = "linked_list_allocator"),
+    all(feature = "bump_allocator", feature = "fixed_size_block_allocator"),
+    all(feature = "linked_list_allocator", feature = "fixed_size_block_allocator"),
+))]
+compile_error!("enable only one of the bump_allocator, linked_list_allocator and fixed_size_block_allocator features");
+
+#[cfg(not(any(
+    feature = "bump_allocator",
+    feature = "linked_list_allocator",
+    feature = "fixed_size_block_allocator"
+)))]
+compile_error!("enable one of the bump_allocator, linked_list_allocator and fixed_size_block_allocator features");
+
+#[cfg(feature = "bump_allocator")]
+type SelectedAllocator = bump::BumpAllocator;
+#[cfg(feature = "linked_list_allocator")]
+type SelectedAllocator = linked_list::LinkedListAllocator;
+#[cfg(feature = "fixed_size_block_allocator")]
+type SelectedAllocator = fixed_size_block::FixedSizeBlockAllocator;
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator<SelectedAllocator> = KernelAllocator::new(SelectedAllocator::new());
+
+/// Hands the heap memory to the global allocator. See memory::init_heap for mapping it.
+///
+/// Unsafe because the caller must guarantee that the given range is mapped, unused
+/// and that this is called only once.
+pub unsafe fn init(heap_start: usize, heap_size: usize) {
+    ALLOCATOR.inner.lock().init(heap_start, heap_size);
+}
+
+/// Returns the counters of the global allocator.
+pub fn stats() -> AllocStats {
+    ALLOCATOR.stats()
+}
+
+/// True if the most recent allocation failed, i.e. a panic right now is most likely because the heap ran out.
+/// Unlike failed_allocations, an earlier failure that was handled (e.g. with Vec::try_reserve) does not count.
+pub fn last_allocation_failed() -> bool {
+    ALLOCATOR.last_allocation_failed.load(Ordering::Relaxed)
+}
+
+/// Interface every built-in allocator implements. Locking and statistics are taken care of by
+/// KernelAllocator, so the methods here take &mut self.
+pub trait HeapAllocator {
+    /// Initializes the allocator with the given heap bounds.
+    ///
+    /// Unsafe because the caller must guarantee that the given memory range is unused.
+    unsafe fn init(&mut self, heap_start: usize, heap_size: usize);
+
+    /// Returns a null pointer if the request cannot be satisfied.
+    unsafe fn alloc(&mut self, layout: Layout) -> *mut u8;
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout);
+}
+
+/// Snapshot of the allocation counters.
+#[derive(Debug, Clone, Copy)]
+pub struct AllocStats {
+    pub bytes_in_use: usize,
+    pub peak_bytes_in_use: usize,
+    pub allocations: usize,
+    pub failed_allocations: usize,
+}
+
+/// Wraps a HeapAllocator with a spin lock and allocation counters so it can be the global allocator.
+pub struct KernelAllocator<A: HeapAllocator> {
+    inner: Mutex<A>,
+    bytes_in_use: AtomicUsize,
+    peak_bytes_in_use: AtomicUsize,
+    allocations: AtomicUsize,
+    failed_allocations: AtomicUsize,
+    last_allocation_failed: AtomicBool,
+}
+
+impl<A: HeapAllocator> KernelAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        KernelAllocator {
+            inner: Mutex::new(inner),
+            bytes_in_use: AtomicUsize::new(0),
+            peak_bytes_in_use: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0),
+            failed_allocations: AtomicUsize::new(0),
+            last_allocation_failed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn stats(&self) -> AllocStats {
+        AllocStats {
+            bytes_in_use: self.bytes_in_use.load(Ordering::Relaxed),
+            peak_bytes_in_use: self.peak_bytes_in_use.load(Ordering::Relaxed),
+            allocations: self.allocations.load(Ordering::Relaxed),
+            failed_allocations: self.failed_allocations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+unsafe impl<A: HeapAllocator> GlobalAlloc for KernelAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.lock().alloc(layout);
+        self.last_allocation_failed.store(ptr.is_null(), Ordering::Relaxed);
+        if ptr.is_null() {
+            self.failed_allocations.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            let in_use = self.bytes_in_use.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            self.peak_bytes_in_use.fetch_max(in_use, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.inner.lock().dealloc(ptr, layout);
+        self.bytes_in_use.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// Prints the allocation counters. Called from the panic handler if last_allocation_failed, as an
+/// allocation failure ends in a panic ("memory allocation of N bytes failed") in no_std.
+pub fn report_heap_exhaustion() {
+    let stats = stats();
+    println!("HEAP EXHAUSTED");
+    println!(" bytes in use:       {}", stats.bytes_in_use);
+    println!(" peak bytes in use:  {}", stats.peak_bytes_in_use);
+    println!(" allocations:        {}", stats.allocations);
+    println!(" failed allocations: {}", stats.failed_allocations);
+}
+
+/// Align the given address upwards to the given alignment, which must be a power of two.
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{boxed::Box, vec::Vec};
+
+    #[test_case]
+    fn simple_allocation() {
+        let heap_value_1 = Box::new(41);
+        let heap_value_2 = Box::new(13);
+        assert_eq!(*heap_value_1, 41);
+        assert_eq!(*heap_value_2, 13);
+    }
+
+    #[test_case]
+    fn large_vec() {
+        let n = 1000;
+        let mut vec = Vec::new();
+        for i in 0..n {
+            vec.push(i);
+        }
+        assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+    }
+
+    #[test_case]
+    fn many_boxes() {
+        //allocates just more than the heap size in total, so only succeeds if freed memory is reused
+        for i in 0..crate::memory::HEAP_SIZE / 64 + 1 {
+            let x = Box::new([i; 64 / core::mem::size_of::<usize>()]);
+            assert_eq!(x[0], i);
+        }
+    }
+
+    #[test_case]
+    fn stats_track_allocations() {
+        let before = stats();
+        let x = Box::new([0u8; 64]);
+        let during = stats();
+        assert_eq!(during.allocations, before.allocations + 1);
+        assert_eq!(during.bytes_in_use, before.bytes_in_use + 64);
+        assert!(during.peak_bytes_in_use >= during.bytes_in_use);
+        drop(x);
+        assert_eq!(stats().bytes_in_use, before.bytes_in_use);
+    }
+
+    #[test_case]
+    fn only_the_last_allocation_counts_as_exhaustion() {
+        let mut too_big: Vec<u8> = Vec::new();
+        assert!(too_big.try_reserve_exact(crate::memory::HEAP_SIZE + 1).is_err());
+        assert!(last_allocation_failed());
+        let _fits = Box::new(1);
+        assert!(!last_allocation_failed());
+    }
+}
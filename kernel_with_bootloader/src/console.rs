@@ -0,0 +1,143 @@
+//Where print! and friends write to. The framebuffer is preferred, but the bootloader does not always
+//provide one (e.g. with some BIOS setups), so output can also go to the VGA text buffer or the serial port.
+//init picks the first one that is available at boot. Until then output goes to the serial port.
+//Ref: https://os.phil-opp.com/vga-text-mode/
+
+mod vga_text;
+
+use core::fmt::{self, Write};
+
+use bootloader_api::info::FrameBuffer;
+use spin::Mutex;
+use vga_text::VgaText;
+
+use crate::writer::Color;
+
+/// Something that shows the console output.
+pub trait ConsoleSink: Sync {
+    /// Short name for logs, e.g. "framebuffer".
+    fn name(&self) -> &'static str;
+
+    /// Writes to the given virtual terminal. Sinks without virtual terminals show what is written
+    /// to any of them.
+    fn print(&self, terminal: usize, args: fmt::Arguments);
+
+    /// Like print, in the given foreground color. Sinks without colors write plain text.
+    fn print_colored(&self, terminal: usize, color: Color, args: fmt::Arguments) {
+        let _ = color;
+        self.print(terminal, args);
+    }
+
+    /// True if this is the serial port, i.e. what is printed already goes where serial_print! writes to.
+    fn is_serial(&self) -> bool {
+        false
+    }
+}
+
+/// The FrameBufferWriter, with its virtual terminals, scrollback and fonts.
+struct FramebufferSink;
+
+impl ConsoleSink for FramebufferSink {
+    fn name(&self) -> &'static str {
+        "framebuffer"
+    }
+
+    fn print(&self, terminal: usize, args: fmt::Arguments) {
+        crate::FRAME_BUFFER_WRITER.lock().terminal(terminal).write_fmt(args).unwrap();
+    }
+
+    fn print_colored(&self, terminal: usize, color: Color, args: fmt::Arguments) {
+        let mut writer = crate::FRAME_BUFFER_WRITER.lock();
+        let mut terminal = writer.terminal(terminal);
+        let (foreground, background) = terminal.color();
+        terminal.set_color(color, background);
+        terminal.write_fmt(args).unwrap();
+        terminal.set_color(foreground, background);
+    }
+}
+
+/// 80x25 chars in 16 colors, see vga_text.
+struct VgaTextSink;
+
+static VGA_TEXT: Mutex<Option<VgaText>> = Mutex::new(None);
+
+impl ConsoleSink for VgaTextSink {
+    fn name(&self) -> &'static str {
+        "VGA text mode"
+    }
+
+    fn print(&self, _terminal: usize, args: fmt::Arguments) {
+        if let Some(vga_text) = VGA_TEXT.lock().as_mut() {
+            vga_text.write_fmt(args).unwrap();
+        }
+    }
+
+    fn print_colored(&self, _terminal: usize, color: Color, args: fmt::Arguments) {
+        if let Some(vga_text) = VGA_TEXT.lock().as_mut() {
+            let attribute = vga_text.attribute();
+            vga_text.set_foreground(color);
+            vga_text.write_fmt(args).unwrap();
+            vga_text.set_attribute(attribute);
+        }
+    }
+}
+
+/// COM1, see the serial module. Also where serial_print! writes to.
+struct SerialSink;
+
+impl ConsoleSink for SerialSink {
+    fn name(&self) -> &'static str {
+        "serial port"
+    }
+
+    fn print(&self, _terminal: usize, args: fmt::Arguments) {
+        crate::serial::SERIAL1.lock().write_fmt(args).unwrap();
+    }
+
+    fn print_colored(&self, _terminal: usize, color: Color, args: fmt::Arguments) {
+        //the terminal on the other end understands ANSI escape sequences, so use a 24 bit color
+        let (r, g, b) = (color.r, color.g, color.b);
+        write!(crate::serial::SERIAL1.lock(), "\x1b[38;2;{r};{g};{b}m{args}\x1b[39m").unwrap();
+    }
+
+    fn is_serial(&self) -> bool {
+        true
+    }
+}
+
+static SINK: Mutex<&'static dyn ConsoleSink> = Mutex::new(&SerialSink);
+
+/// Chooses where console output goes: the framebuffer if the bootloader set one up, else the VGA text
+/// buffer if there is one, else the serial port. physical_memory_offset is needed to reach the VGA text
+/// buffer. Returns the chosen sink.
+pub fn init(framebuffer: Option<&'static mut FrameBuffer>, physical_memory_offset: Option<u64>) -> &'static dyn ConsoleSink {
+    let vga_text = || physical_memory_offset.and_then(|offset| unsafe { VgaText::probe(offset) });
+    let sink: &'static dyn ConsoleSink = if let Some(framebuffer) = framebuffer {
+        let info = framebuffer.info();
+        crate::FRAME_BUFFER_WRITER.lock().init(framebuffer.buffer_mut(), info);
+        &FramebufferSink
+    } else if let Some(vga_text) = vga_text() {
+        *VGA_TEXT.lock() = Some(vga_text);
+        &VgaTextSink
+    } else {
+        &SerialSink
+    };
+    *SINK.lock() = sink;
+    sink
+}
+
+/// Returns where print! writes to.
+pub fn sink() -> &'static dyn ConsoleSink {
+    *SINK.lock() //copied out, so the lock is not held while printing
+}
+
+/// Used by the print macros in std.rs.
+#[doc(hidden)]
+pub fn _print(terminal: usize, args: fmt::Arguments) {
+    sink().print(terminal, args);
+}
+
+#[doc(hidden)]
+pub fn _print_colored(terminal: usize, color: Color, args: fmt::Arguments) {
+    sink().print_colored(terminal, color, args);
+}
@@ -0,0 +1,213 @@
+//VGA text mode: the screen is 80x25 cells at physical address 0xB8000. Each cell is a char of
+//code page 437 in the low byte and an attribute in the high byte, with the foreground color in
+//the low and the background color in the high nibble. Only there when booted by BIOS.
+//Of the escape sequences, only SGR colors and the cursor movement used by read_line are understood.
+//Ref: https://os.phil-opp.com/vga-text-mode/ and https://en.wikipedia.org/wiki/VGA_text_mode
+
+use core::fmt;
+
+use crate::writer::ansi::{Action, Parser};
+use crate::writer::Color;
+
+const BUFFER_ADDRESS: u64 = 0xB8000;
+const WIDTH: usize = 80;
+const HEIGHT: usize = 25;
+
+/// Light gray on black, what the BIOS uses.
+const DEFAULT_ATTRIBUTE: u8 = 0x07;
+
+/// Written to a cell to find out if there is a text buffer, see probe.
+const PROBE_CELL: u16 = 0x5A3C;
+
+/// VGA color number of each of the 8 basic ANSI colors. The bright ones are 8 more in both.
+const ANSI_TO_VGA: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+pub struct VgaText {
+    buffer: *mut u16,
+    column: usize,
+    row: usize,
+    attribute: u8,
+    ansi_parser: Parser,
+}
+
+//the buffer is only accessed through the Mutex in console.rs
+unsafe impl Send for VgaText {}
+
+impl VgaText {
+    /// Returns the VGA text buffer, cleared, if there is one.
+    ///
+    /// Unsafe because all physical memory must be mapped at physical_memory_offset.
+    pub unsafe fn probe(physical_memory_offset: u64) -> Option<Self> {
+        let buffer = (physical_memory_offset + BUFFER_ADDRESS) as *mut u16;
+        //without VGA hardware nothing keeps what is written there. Reads usually give 0xFFFF
+        let cell = buffer.add(WIDTH * HEIGHT - 1);
+        let old = cell.read_volatile();
+        cell.write_volatile(PROBE_CELL);
+        let present = cell.read_volatile() == PROBE_CELL;
+        cell.write_volatile(old);
+        if !present {
+            return None;
+        }
+        let mut vga_text = VgaText {
+            buffer,
+            column: 0,
+            row: 0,
+            attribute: DEFAULT_ATTRIBUTE,
+            ansi_parser: Parser::new(),
+        };
+        vga_text.clear();
+        Some(vga_text)
+    }
+
+    pub fn attribute(&self) -> u8 {
+        self.attribute
+    }
+
+    pub fn set_attribute(&mut self, attribute: u8) {
+        self.attribute = attribute;
+    }
+
+    /// Sets the foreground to the VGA color closest to color.
+    pub fn set_foreground(&mut self, color: Color) {
+        self.attribute = self.attribute & 0xF0 | vga_color(color);
+    }
+
+    fn set_background(&mut self, color: Color) {
+        self.attribute = self.attribute & 0x0F | vga_color(color) << 4;
+    }
+
+    fn put(&mut self, column: usize, row: usize, byte: u8) {
+        let cell = (self.attribute as u16) << 8 | byte as u16;
+        unsafe { self.buffer.add(row * WIDTH + column).write_volatile(cell) };
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        for column in 0..WIDTH {
+            self.put(column, row, b' ');
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for row in 0..HEIGHT {
+            self.clear_row(row);
+        }
+        self.column = 0;
+        self.row = 0;
+    }
+
+    fn scroll_up(&mut self) {
+        for i in WIDTH..WIDTH * HEIGHT {
+            unsafe {
+                let cell = self.buffer.add(i).read_volatile();
+                self.buffer.add(i - WIDTH).write_volatile(cell);
+            }
+        }
+        self.clear_row(HEIGHT - 1);
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        if self.row + 1 < HEIGHT {
+            self.row += 1;
+        } else {
+            self.scroll_up();
+        }
+    }
+
+    fn write_char(&mut self, c: char) {
+        match c {
+            '\n' => self.newline(),
+            '\r' => self.column = 0,
+            '\u{0008}' => {
+                //backspace, within the line
+                if self.column > 0 {
+                    self.column -= 1;
+                    self.put(self.column, self.row, b' ');
+                }
+            }
+            c => {
+                if self.column >= WIDTH {
+                    self.newline();
+                }
+                self.put(self.column, self.row, to_code_page_437(c));
+                self.column += 1;
+            }
+        }
+    }
+
+    /// SGR with the 16 basic colors.
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.attribute = DEFAULT_ATTRIBUTE;
+        }
+        for &param in params {
+            match param {
+                0 => self.attribute = DEFAULT_ATTRIBUTE,
+                code @ 30..=37 => self.set_foreground(Color::from_ansi_256((code - 30) as u8)),
+                39 => self.attribute = self.attribute & 0xF0 | DEFAULT_ATTRIBUTE & 0x0F,
+                code @ 40..=47 => self.set_background(Color::from_ansi_256((code - 40) as u8)),
+                49 => self.attribute = self.attribute & 0x0F | DEFAULT_ATTRIBUTE & 0xF0,
+                code @ 90..=97 => self.set_foreground(Color::from_ansi_256((code - 90 + 8) as u8)),
+                code @ 100..=107 => self.set_background(Color::from_ansi_256((code - 100 + 8) as u8)),
+                38 | 48 => break, //256 and 24 bit colors are not supported
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Write for VgaText {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            match self.ansi_parser.advance(c) {
+                Action::Print(c) => self.write_char(c),
+                Action::Csi(sequence) if !sequence.private => {
+                    let n = sequence.param_or(0, 1) as usize;
+                    match sequence.final_char {
+                        'C' => self.column = (self.column + n).min(WIDTH - 1), //CUF
+                        'D' => self.column = self.column.saturating_sub(n), //CUB
+                        'K' => {
+                            //EL from the cursor to the end of the line, as used by read_line
+                            for column in self.column..WIDTH {
+                                self.put(column, self.row, b' ');
+                            }
+                        }
+                        'm' => self.select_graphic_rendition(sequence.params()),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The VGA color number (0 to 15) closest to color.
+fn vga_color(color: Color) -> u8 {
+    let ansi = color.to_ansi_16();
+    ANSI_TO_VGA[(ansi % 8) as usize] | ansi & 8
+}
+
+/// Chars outside of printable ASCII are shown as a small square.
+fn to_code_page_437(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        _ => 0xFE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn ansi_colors_map_to_vga_colors() {
+        assert_eq!(vga_color(Color::BLACK), 0);
+        assert_eq!(vga_color(Color::from_ansi_256(4)), 1); //blue
+        assert_eq!(vga_color(Color::RED), 12); //bright red
+        assert_eq!(vga_color(Color::new(250, 250, 250)), 15);
+        assert_eq!(to_code_page_437('A'), b'A');
+        assert_eq!(to_code_page_437('é'), 0xFE);
+    }
+}
@@ -0,0 +1,76 @@
+//Our own Global Descriptor Table with a Task State Segment.
+//The TSS holds the Interrupt Stack Table (IST): known good stacks the CPU switches to
+//before calling the handler of an exception. Without it, a kernel stack overflow causes a page fault
+//that cannot push its stack frame, then a double fault that cannot either, and QEMU resets (triple fault).
+//Ref: https://os.phil-opp.com/double-fault-exceptions/
+
+use lazy_static::lazy_static;
+use x86_64::instructions::segmentation::{Segment, CS, DS, ES, SS};
+use x86_64::instructions::tables::load_tss;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+
+//IST indexes used by interrupts.rs
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+/// Size of each IST stack. Big enough for exception_report's formatting.
+const IST_STACK_SIZE: usize = 4096 * 5; // 20 KiB
+
+/// Returns the top of the given stack, as stacks grow downwards on x86.
+fn stack_end(stack: &'static [u8; IST_STACK_SIZE]) -> VirtAddr {
+    let stack_start = VirtAddr::from_ptr(stack);
+    stack_start + IST_STACK_SIZE
+}
+
+lazy_static! {
+    static ref TSS: TaskStateSegment = {
+        //no guard pages below these stacks, so the handlers using them must not overflow them
+        static mut DOUBLE_FAULT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+        static mut NMI_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+        static mut MACHINE_CHECK_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+
+        let mut tss = TaskStateSegment::new();
+        //the stacks are only ever used by the CPU, never through these references
+        unsafe {
+            tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+                stack_end(&*core::ptr::addr_of!(DOUBLE_FAULT_STACK));
+            tss.interrupt_stack_table[NMI_IST_INDEX as usize] =
+                stack_end(&*core::ptr::addr_of!(NMI_STACK));
+            tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+                stack_end(&*core::ptr::addr_of!(MACHINE_CHECK_STACK));
+        }
+        tss
+    };
+}
+
+struct Selectors {
+    code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
+    tss_selector: SegmentSelector,
+}
+
+lazy_static! {
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let mut gdt = GlobalDescriptorTable::new();
+        let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        (gdt, Selectors { code_selector, data_selector, tss_selector })
+    };
+}
+
+/// Loads our GDT and TSS. Must be called before interrupts::init, as the IDT refers to the IST stacks.
+pub fn init() {
+    GDT.0.load();
+    unsafe {
+        //the segment registers still hold selectors of the bootloader's GDT, so reload them
+        CS::set_reg(GDT.1.code_selector);
+        SS::set_reg(GDT.1.data_selector);
+        DS::set_reg(GDT.1.data_selector);
+        ES::set_reg(GDT.1.data_selector);
+        load_tss(GDT.1.tss_selector);
+    }
+}
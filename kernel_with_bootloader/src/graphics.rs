@@ -0,0 +1,402 @@
+//2D drawing on the framebuffer: pixels, lines, rectangles, circles and sprites.
+//The framebuffer belongs to FRAME_BUFFER_WRITER, so drawing goes through it (see draw below) and
+//ends up in its back buffer like the text. Use set_text_region to keep the text out of the area
+//you draw in.
+//Ref: https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm and
+//https://en.wikipedia.org/wiki/Midpoint_circle_algorithm
+
+use bootloader_api::info::FrameBufferInfo;
+
+pub use crate::writer::Color;
+
+/// A rectangle of pixels, e.g. a clipping area or the text region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect { x, y, width, height }
+    }
+
+    /// x of the first column right of the rectangle.
+    pub const fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    /// y of the first row below the rectangle.
+    pub const fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the part that is in both rectangles, which is empty if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        Rect::new(x, y, right.saturating_sub(x), bottom.saturating_sub(y))
+    }
+
+    /// Returns the smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+}
+
+/// An image with 4 bytes per pixel in the order red, green, blue, alpha, row by row.
+/// Alpha 0 is transparent and 255 opaque. rgba must hold width * height pixels, else blit draws nothing.
+pub struct Sprite<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: &'a [u8],
+}
+
+/// Drawing surface for the framebuffer. Coordinates are signed so that shapes can be partly
+/// off-screen; everything is clipped to the clipping rectangle, which is the whole screen by default.
+///
+/// Nothing is drawn if the framebuffer has a pixel format that Color::to_pixel does not support.
+pub struct Canvas<'a> {
+    pixels: &'a mut [u8],
+    info: FrameBufferInfo,
+    clip: Rect,
+    damage: Rect, //bounding box of everything drawn, so that only it has to be flushed
+}
+
+#[allow(dead_code)] //a drawing API, the kernel itself only uses part of it
+impl<'a> Canvas<'a> {
+    /// pixels must have the layout described by info, e.g. the framebuffer or a copy of it.
+    pub fn new(pixels: &'a mut [u8], info: FrameBufferInfo) -> Self {
+        Canvas {
+            pixels,
+            info,
+            clip: Rect::new(0, 0, info.width, info.height),
+            damage: Rect::new(0, 0, 0, 0),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.info.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.info.height
+    }
+
+    /// Restricts drawing to the given rectangle (and the screen).
+    pub fn set_clip(&mut self, clip: Rect) {
+        self.clip = clip.intersection(&Rect::new(0, 0, self.info.width, self.info.height));
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// The area drawn into since the canvas was created.
+    pub fn damage(&self) -> Rect {
+        self.damage
+    }
+
+    /// Returns the byte offset of the pixel if it is inside the clipping rectangle.
+    fn offset(&self, x: isize, y: isize) -> Option<usize> {
+        if x < self.clip.x as isize
+            || y < self.clip.y as isize
+            || x >= self.clip.right() as isize
+            || y >= self.clip.bottom() as isize
+        {
+            return None;
+        }
+        Some((y as usize * self.info.stride + x as usize) * self.info.bytes_per_pixel)
+    }
+
+    pub fn put_pixel(&mut self, x: isize, y: isize, color: Color) {
+        let (Some(offset), Some(pixel)) = (self.offset(x, y), color.to_pixel(self.info.pixel_format)) else {
+            return;
+        };
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        self.pixels[offset..offset + bytes_per_pixel].copy_from_slice(&pixel[..bytes_per_pixel]);
+        self.damage = self.damage.union(&Rect::new(x as usize, y as usize, 1, 1));
+    }
+
+    /// Returns the color of a pixel, None if it is outside of the clipping rectangle.
+    pub fn get_pixel(&self, x: isize, y: isize) -> Option<Color> {
+        let offset = self.offset(x, y)?;
+        Color::from_pixel(&self.pixels[offset..offset + self.info.bytes_per_pixel], self.info.pixel_format)
+    }
+
+    /// Fills the whole clipping rectangle.
+    pub fn clear(&mut self, color: Color) {
+        let clip = self.clip;
+        self.fill_rect(clip.x as isize, clip.y as isize, clip.width, clip.height, color);
+    }
+
+    /// Draws a line from (x0, y0) to (x1, y1), both ends included, with Bresenham's algorithm.
+    /// Only the part inside the clipping rectangle is walked, see clip_line.
+    pub fn draw_line(&mut self, x0: isize, y0: isize, x1: isize, y1: isize, color: Color) {
+        let Some((x0, y0, x1, y1)) = self.clip_line(x0, y0, x1, y1) else {
+            return;
+        };
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.put_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if error2 <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Cuts a line to the clipping rectangle with the Cohen-Sutherland algorithm: an end outside is
+    /// moved along the line onto the edge it is beyond, until both ends are inside. Returns None if
+    /// the line misses the rectangle. The cut ends are rounded to whole pixels.
+    /// Ref: https://en.wikipedia.org/wiki/Cohen%E2%80%93Sutherland_algorithm
+    fn clip_line(&self, x0: isize, y0: isize, x1: isize, y1: isize) -> Option<(isize, isize, isize, isize)> {
+        const LEFT: u8 = 1;
+        const RIGHT: u8 = 2;
+        const TOP: u8 = 4;
+        const BOTTOM: u8 = 8;
+        if self.clip.is_empty() {
+            return None;
+        }
+        //i128, so that the products below can not overflow
+        let (left, top) = (self.clip.x as i128, self.clip.y as i128);
+        let (right, bottom) = (self.clip.right() as i128 - 1, self.clip.bottom() as i128 - 1);
+        let outcode = |x: i128, y: i128| {
+            let horizontal = if x < left { LEFT } else if x > right { RIGHT } else { 0 };
+            let vertical = if y < top { TOP } else if y > bottom { BOTTOM } else { 0 };
+            horizontal | vertical
+        };
+        //a / b rounded to the nearest whole number
+        let divide = |a: i128, b: i128| (2 * a + a.signum() * b.abs()) / (2 * b);
+        let (mut x0, mut y0, mut x1, mut y1) = (x0 as i128, y0 as i128, x1 as i128, y1 as i128);
+        let (mut code0, mut code1) = (outcode(x0, y0), outcode(x1, y1));
+        //two cuts per end, plus a few in case rounding lands just outside an edge
+        for _ in 0..8 {
+            if code0 | code1 == 0 {
+                return Some((x0 as isize, y0 as isize, x1 as isize, y1 as isize));
+            }
+            if code0 & code1 != 0 {
+                return None; //both ends beyond the same edge
+            }
+            let code = if code0 != 0 { code0 } else { code1 };
+            let (x, y) = if code & TOP != 0 {
+                (x0 + divide((x1 - x0) * (top - y0), y1 - y0), top)
+            } else if code & BOTTOM != 0 {
+                (x0 + divide((x1 - x0) * (bottom - y0), y1 - y0), bottom)
+            } else if code & RIGHT != 0 {
+                (right, y0 + divide((y1 - y0) * (right - x0), x1 - x0))
+            } else {
+                (left, y0 + divide((y1 - y0) * (left - x0), x1 - x0))
+            };
+            if code == code0 {
+                (x0, y0, code0) = (x, y, outcode(x, y));
+            } else {
+                (x1, y1, code1) = (x, y, outcode(x, y));
+            }
+        }
+        None
+    }
+
+    /// Draws the outline of a rectangle.
+    pub fn draw_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let right = x + width as isize - 1;
+        let bottom = y + height as isize - 1;
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, bottom, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(right, y, 1, height, color);
+    }
+
+    /// Fills a rectangle. Clipped first, then filled row by row.
+    pub fn fill_rect(&mut self, x: isize, y: isize, width: usize, height: usize, color: Color) {
+        let Some(pixel) = color.to_pixel(self.info.pixel_format) else {
+            return;
+        };
+        //clip with signed coordinates, as x and y may be negative
+        let clip = self.clip;
+        let x_start = x.max(clip.x as isize);
+        let y_start = y.max(clip.y as isize);
+        let x_end = (x + width as isize).min(clip.right() as isize);
+        let y_end = (y + height as isize).min(clip.bottom() as isize);
+        if x_start >= x_end || y_start >= y_end {
+            return;
+        }
+        let (x_start, y_start, x_end, y_end) = (x_start as usize, y_start as usize, x_end as usize, y_end as usize);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        for row in y_start..y_end {
+            let row_start = (row * self.info.stride + x_start) * bytes_per_pixel;
+            let row_end = (row * self.info.stride + x_end) * bytes_per_pixel;
+            for pixel_bytes in self.pixels[row_start..row_end].chunks_exact_mut(bytes_per_pixel) {
+                pixel_bytes.copy_from_slice(&pixel[..bytes_per_pixel]);
+            }
+        }
+        let drawn = Rect::new(x_start, y_start, x_end - x_start, y_end - y_start);
+        self.damage = self.damage.union(&drawn);
+    }
+
+    /// Draws the outline of a circle with the midpoint algorithm.
+    pub fn draw_circle(&mut self, center_x: isize, center_y: isize, radius: usize, color: Color) {
+        let mut x = radius as isize;
+        let mut y = 0;
+        let mut error = 1 - x;
+        while x >= y {
+            //one point in each octant
+            for (dx, dy) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                self.put_pixel(center_x + dx, center_y + dy, color);
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Fills a circle, with horizontal lines between the points of the outline.
+    pub fn fill_circle(&mut self, center_x: isize, center_y: isize, radius: usize, color: Color) {
+        let mut x = radius as isize;
+        let mut y = 0;
+        let mut error = 1 - x;
+        while x >= y {
+            for (half_width, dy) in [(x, y), (x, -y), (y, x), (y, -x)] {
+                let width = (2 * half_width + 1) as usize;
+                self.fill_rect(center_x - half_width, center_y + dy, width, 1, color);
+            }
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Draws a sprite with its top left corner at (x, y), blended over what is already there
+    /// according to its alpha channel.
+    pub fn blit(&mut self, x: isize, y: isize, sprite: &Sprite) {
+        let pixels = sprite.width.checked_mul(sprite.height).and_then(|pixels| pixels.checked_mul(4));
+        if pixels.map_or(true, |bytes| sprite.rgba.len() < bytes) {
+            return; //rgba is too short for the size
+        }
+        for row in 0..sprite.height {
+            for column in 0..sprite.width {
+                let i = (row * sprite.width + column) * 4;
+                let [r, g, b, alpha] = [sprite.rgba[i], sprite.rgba[i + 1], sprite.rgba[i + 2], sprite.rgba[i + 3]];
+                let (px, py) = (x + column as isize, y + row as isize);
+                match alpha {
+                    0 => {} //transparent
+                    255 => self.put_pixel(px, py, Color::new(r, g, b)),
+                    alpha => {
+                        if let Some(below) = self.get_pixel(px, py) {
+                            self.put_pixel(px, py, below.blend(Color::new(r, g, b), alpha));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws on the framebuffer and shows the result, e.g.
+/// graphics::draw(|canvas| canvas.fill_circle(100, 100, 50, Color::RED));
+#[allow(dead_code)] //for the programs we write, see the commented examples in main.rs
+pub fn draw<R>(draw: impl FnOnce(&mut Canvas) -> R) -> R {
+    crate::FRAME_BUFFER_WRITER.lock().draw(draw)
+}
+
+/// Keeps print! and friends inside region, leaving the rest of the screen for drawing.
+#[allow(dead_code)]
+pub fn set_text_region(region: Rect) {
+    crate::FRAME_BUFFER_WRITER.lock().set_text_region(region);
+}
+
+#[allow(dead_code)]
+pub fn text_region() -> Rect {
+    crate::FRAME_BUFFER_WRITER.lock().text_region()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use bootloader_api::info::PixelFormat;
+
+    #[test_case]
+    fn shapes_are_clipped_and_packed() {
+        let info = FrameBufferInfo {
+            byte_len: 8 * 4 * 4,
+            width: 6,
+            height: 4,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 4,
+            stride: 8, //padding at the end of each row must not be drawn into
+        };
+        let mut pixels = vec![0u8; info.byte_len];
+        let mut canvas = Canvas::new(&mut pixels, info);
+        canvas.draw_line(-2, 0, 10, 0, Color::new(1, 2, 3));
+        canvas.fill_rect(4, 2, 10, 10, Color::WHITE);
+        assert_eq!(canvas.get_pixel(0, 0), Some(Color::new(1, 2, 3)));
+        assert_eq!(canvas.get_pixel(6, 0), None);
+        assert_eq!(canvas.damage(), Rect::new(0, 0, 6, 4));
+        assert_eq!(&pixels[..4], &[3, 2, 1, 0]);
+        assert_eq!(&pixels[6 * 4..8 * 4], &[0; 8]);
+    }
+
+    #[test_case]
+    fn long_lines_and_short_sprites_are_safe() {
+        let info = FrameBufferInfo {
+            byte_len: 4 * 4 * 4,
+            width: 4,
+            height: 4,
+            pixel_format: PixelFormat::Bgr,
+            bytes_per_pixel: 4,
+            stride: 4,
+        };
+        let mut pixels = vec![0u8; info.byte_len];
+        let mut canvas = Canvas::new(&mut pixels, info);
+        //would take ages if every point off the screen was walked
+        canvas.draw_line(-1_000_000_000, -1_000_000_000, 1_000_000_000, 1_000_000_000, Color::WHITE);
+        assert_eq!(canvas.damage(), Rect::new(0, 0, 4, 4));
+        assert_eq!(canvas.get_pixel(3, 3), Some(Color::WHITE));
+        assert_eq!(canvas.get_pixel(3, 0), Some(Color::new(0, 0, 0)));
+        canvas.draw_line(-10, -1, 10, -1, Color::WHITE); //above the screen
+        canvas.blit(0, 0, &Sprite { width: 2, height: 2, rgba: &[255; 15] });
+        assert_eq!(canvas.damage(), Rect::new(0, 0, 4, 4));
+        assert_eq!(canvas.get_pixel(1, 0), Some(Color::new(0, 0, 0)));
+    }
+}
@@ -1,39 +1,215 @@
 use alloc::string::String;
+use core::fmt;
+use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::InterruptStackFrame;
 use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::structures::idt::{PageFaultErrorCode, SelectorErrorCode};
 
-use crate::print;
+use crate::gdt;
 use crate::println;//use your custom println macro.
+use crate::serial_println;
 
-/*In this section we define handlers for interrupts*/
-//1. breakpoint_handler - handles the invocation of INT3
+/*In this section we define handlers for CPU exceptions.
+Every handler prints the same report through exception_report below. Exceptions we can
+continue from (breakpoint, debug, overflow, NMI) return; every other one panics after the report.
+Ref: https://wiki.osdev.org/Exceptions */
+
+/// Error code pushed by the CPU, decoded according to the exception that pushed it.
+enum ExceptionErrorCode {
+    /// Segment selector related exceptions (#TS, #NP, #SS, #GP)
+    Selector(u64),
+    PageFault(PageFaultErrorCode),
+    Raw(u64),
+}
+
+impl fmt::Display for ExceptionErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExceptionErrorCode::Selector(code) => {
+                let selector = SelectorErrorCode::new_truncate(*code);
+                if *code == 0 {
+                    write!(f, "{:#x} (not segment related)", code)
+                } else {
+                    write!(
+                        f,
+                        "{:#x} (table: {:?}, index: {}, external: {})",
+                        code,
+                        selector.descriptor_table(),
+                        selector.index(),
+                        selector.external()
+                    )
+                }
+            }
+            ExceptionErrorCode::PageFault(code) => write!(f, "{:#x} ({:?})", code.bits(), code),
+            ExceptionErrorCode::Raw(code) => write!(f, "{:#x}", code),
+        }
+    }
+}
+
+/// Prints the uniform diagnostic dump of an exception, to the screen and to the serial port.
+fn exception_report(
+    name: &str,
+    vector: u8,
+    error_code: Option<ExceptionErrorCode>,
+    stack_frame: &InterruptStackFrame,
+) {
+    println!("\nEXCEPTION: {} (vector {})", name, vector);
+    serial_println!("\nEXCEPTION: {} (vector {})", name, vector);
+    if let Some(error_code) = &error_code {
+        println!(" Error Code: {}", error_code);
+        serial_println!(" Error Code: {}", error_code);
+    }
+    if let Some(ExceptionErrorCode::PageFault(_)) = error_code {
+        //CR2 holds the virtual address whose access caused the page fault
+        let accessed_address = Cr2::read();
+        println!(" Accessed Address (CR2): {:?}", accessed_address);
+        serial_println!(" Accessed Address (CR2): {:?}", accessed_address);
+    }
+    println!(" Stack Frame:\n{:#?}", stack_frame);
+    serial_println!(" Stack Frame:\n{:#?}", stack_frame);
+}
+
+//0. Divide error, e.g. division by zero
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    exception_report("DIVIDE ERROR", 0, None, &stack_frame);
+    panic!("EXCEPTION: DIVIDE ERROR");
+}
+
+//1. Debug, e.g. single stepping. We can continue
+extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    exception_report("DEBUG", 1, None, &stack_frame);
+}
+
+//2. Non-maskable interrupt, usually a hardware failure report. We can continue
+extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
+    exception_report("NON-MASKABLE INTERRUPT", 2, None, &stack_frame);
+}
+
+//3. breakpoint_handler - handles the invocation of INT3. We can continue
 extern "x86-interrupt" fn breakpoint_handler(
     stack_frame: InterruptStackFrame)
 {
-    println!("EXCEPTION: BREAKPOINT\n Stack Frame:\n {:#?}", stack_frame);
+    exception_report("BREAKPOINT", 3, None, &stack_frame);
+}
+
+//4. Overflow - INTO executed with the overflow flag set. We can continue
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    exception_report("OVERFLOW", 4, None, &stack_frame);
+}
+
+//5. Bound range exceeded
+extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+    exception_report("BOUND RANGE EXCEEDED", 5, None, &stack_frame);
+    panic!("EXCEPTION: BOUND RANGE EXCEEDED");
+}
+
+//6. Invalid opcode handler
+extern "x86-interrupt" fn invalid_opcode_handler(
+    stack_frame: InterruptStackFrame)
+{
+    exception_report("INVALID OPCODE", 6, None, &stack_frame);
+    panic!("EXCEPTION: INVALID OPCODE");
 }
 
-//2. double_fault_handler
+//7. Device not available - FPU/SSE instruction while the FPU is disabled
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    exception_report("DEVICE NOT AVAILABLE", 7, None, &stack_frame);
+    panic!("EXCEPTION: DEVICE NOT AVAILABLE");
+}
+
+//8. double_fault_handler
 extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64) -> !
+    stack_frame: InterruptStackFrame, error_code: u64) -> !
 {
-    panic!("EXCEPTION: DOUBLE FAULT\n Stack Frame:\n{:#?}", stack_frame);
+    exception_report("DOUBLE FAULT", 8, Some(ExceptionErrorCode::Raw(error_code)), &stack_frame);
+    panic!("EXCEPTION: DOUBLE FAULT");
+}
+
+//10. Invalid TSS
+extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("INVALID TSS", 10, Some(ExceptionErrorCode::Selector(error_code)), &stack_frame);
+    panic!("EXCEPTION: INVALID TSS");
+}
+
+//11. Segment not present
+extern "x86-interrupt" fn segment_not_present_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("SEGMENT NOT PRESENT", 11, Some(ExceptionErrorCode::Selector(error_code)), &stack_frame);
+    panic!("EXCEPTION: SEGMENT NOT PRESENT");
 }
 
-//3. General protection handler
+//12. Stack segment fault
+extern "x86-interrupt" fn stack_segment_fault_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("STACK SEGMENT FAULT", 12, Some(ExceptionErrorCode::Selector(error_code)), &stack_frame);
+    panic!("EXCEPTION: STACK SEGMENT FAULT");
+}
+
+//13. General protection handler
 extern "x86-interrupt" fn general_protection_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64)
+    stack_frame: InterruptStackFrame, error_code: u64)
 {
-    println!("EXCEPTION: GENERAL PROTECTION\n Error Code: {:#?}\n Stack Frame:\n{:#?}", _error_code, stack_frame);
+    exception_report("GENERAL PROTECTION", 13, Some(ExceptionErrorCode::Selector(error_code)), &stack_frame);
+    panic!("EXCEPTION: GENERAL PROTECTION");
 }
 
-//4. Invalid opcode handler
-extern "x86-interrupt" fn invalid_opcode_handler(
-    stack_frame: InterruptStackFrame)
-{
-    println!("EXCEPTION: INVALID OPCODE\n Stack Frame:\n {:#?}", stack_frame);
+//14. Page fault
+extern "x86-interrupt" fn page_fault_handler(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    exception_report("PAGE FAULT", 14, Some(ExceptionErrorCode::PageFault(error_code)), &stack_frame);
+    panic!("EXCEPTION: PAGE FAULT");
 }
 
+//16. x87 floating point exception
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+    exception_report("x87 FLOATING POINT", 16, None, &stack_frame);
+    panic!("EXCEPTION: x87 FLOATING POINT");
+}
+
+//17. Alignment check - unaligned access with alignment checking enabled
+extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("ALIGNMENT CHECK", 17, Some(ExceptionErrorCode::Raw(error_code)), &stack_frame);
+    panic!("EXCEPTION: ALIGNMENT CHECK");
+}
+
+//18. Machine check - the CPU detected an internal error
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    exception_report("MACHINE CHECK", 18, None, &stack_frame);
+    panic!("EXCEPTION: MACHINE CHECK");
+}
+
+//19. SIMD floating point exception
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    exception_report("SIMD FLOATING POINT", 19, None, &stack_frame);
+    panic!("EXCEPTION: SIMD FLOATING POINT");
+}
+
+//20. Virtualization exception
+extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
+    exception_report("VIRTUALIZATION", 20, None, &stack_frame);
+    panic!("EXCEPTION: VIRTUALIZATION");
+}
+
+//21. Control protection exception
+extern "x86-interrupt" fn cp_protection_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("CONTROL PROTECTION", 21, Some(ExceptionErrorCode::Raw(error_code)), &stack_frame);
+    panic!("EXCEPTION: CONTROL PROTECTION");
+}
+
+//28. Hypervisor injection exception
+extern "x86-interrupt" fn hv_injection_handler(stack_frame: InterruptStackFrame) {
+    exception_report("HYPERVISOR INJECTION", 28, None, &stack_frame);
+    panic!("EXCEPTION: HYPERVISOR INJECTION");
+}
+
+//29. VMM communication exception
+extern "x86-interrupt" fn vmm_communication_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("VMM COMMUNICATION", 29, Some(ExceptionErrorCode::Raw(error_code)), &stack_frame);
+    panic!("EXCEPTION: VMM COMMUNICATION");
+}
+
+//30. Security exception
+extern "x86-interrupt" fn security_exception_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    exception_report("SECURITY EXCEPTION", 30, Some(ExceptionErrorCode::Raw(error_code)), &stack_frame);
+    panic!("EXCEPTION: SECURITY EXCEPTION");
+}
 
 /*Here we setup our Programmable Interrupt Controller
 Ref: Class slides and https://os.phil-opp.com/hardware-interrupts*/
@@ -52,6 +228,20 @@ static PICS: spin::Mutex<ChainedPics> =
 fn init_pics(){
     unsafe { PICS.lock().initialize() };
 }
+
+//IRQ lines on the PICs. A set bit in a mask disables its line
+const CASCADE_IRQ: u8 = 2; //where the slave PIC is connected to the master
+const MOUSE_IRQ: u8 = 12;
+
+/// Unmasks the mouse line on the slave PIC, and the cascade line that the slave PIC's interrupts
+/// come through. The masks are whatever the firmware left, which may have them disabled.
+fn unmask_mouse_irq() {
+    let mut pics = PICS.lock();
+    unsafe {
+        let [master, slave] = pics.read_masks();
+        pics.write_masks(master & !(1 << CASCADE_IRQ), slave & !(1 << (MOUSE_IRQ - 8)));
+    }
+}
 //At this point, calling init_pics() from init() below 
 //will not yet lead to any interrupts because the interrupt
 //enable flag is unset by default.
@@ -63,7 +253,8 @@ fn init_pics(){
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,//offset 0 is reserved for timer
-    Keyboard
+    Keyboard = PIC_1_OFFSET + 1,
+    Mouse = PIC_2_OFFSET + 4, //IRQ12, the 8042's auxiliary device
 }
 
 impl InterruptIndex {
@@ -76,70 +267,48 @@ impl InterruptIndex {
     }
 }
 //Add a handler for Timer
+//It counts the ticks that std::time measures with. See pit.rs for the rate
 extern "x86-interrupt" fn timer_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
+    crate::pit::tick();
     //print!("."); //You can uncomment this to see that timer interrupt is on.
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
     }
 }
-//Below is to hold globally any unicode key pressed on keyboard. It is used 
-//in the keyboard_interrupt_handler function below. 
-//Rather than just keep echoing to screen immediately, we save off in a global variable
-//See input_str function in std.rs for how I interact with it
-//See main.rs from lines 156 to 161 for how I called the input_str
-lazy_static! {
-    pub(crate) static ref KEY_PRESSED: spin::Mutex<Option<char>> =
-        spin::Mutex::new(None);
-}
 //Add a handler for keyboard
+//The handler only reads the scancode and queues it. Decoding is done by task::keyboard::keyboard_task,
+//echoing to screen by whichever task reads the keys. See input_str in std.rs
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Us104Key, ScancodeSet1>> = Mutex::new(
-            Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)
-        );
-    }
-
-    let mut keyboard = KEYBOARD.lock();
     let mut port = Port::new(0x60);
-
     let scancode: u8 = unsafe { port.read() };
-    if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-        if let Some(key) = keyboard.process_keyevent(key_event) {
-            match key {
-                DecodedKey::Unicode(character) => {
-                    match character {
-                        /* Uncomment out below if what you what is immediate display on screen as you press keyboard. Else, let our std::input_str handle it
-                        '\u{0008}'  => {
-                            print!("{}", character);
-                            
-                        },*/
-                        _ => {
-                            unsafe{
-                                KEY_PRESSED.force_unlock();//Give keyboard interrupt absolute priority
-                            }
-                            *KEY_PRESSED.lock() = Some(character);
-                            //print!("{}", character); //Uncomment out this and comment out above (124 to 127) if what you what is immediate display on screen as you press keyboard. Else, let our std::input_str handle it
-                        }
-                    }
-                    
-                },
-                DecodedKey::RawKey(key) => print!("{:?}", key),
-            }
-        }
-    }
+    crate::task::keyboard::add_scancode(scancode);
 
     unsafe {
         PICS.lock()
             .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
     }
 }
+//Add a handler for the mouse
+//Like the keyboard one, it only queues the byte. See task::mouse for the packets they make up.
+//IRQ12 comes through the slave PIC, so both PICs need an EOI; notify_end_of_interrupt sends
+//both for a vector of the slave
+extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let byte: u8 = unsafe { port.read() };
+    crate::task::mouse::add_byte(byte);
+
+    unsafe {
+        PICS.lock()
+            .notify_end_of_interrupt(InterruptIndex::Mouse.as_u8());
+    }
+}
 
 //setup the IDT and make entries of all the handlers
 use lazy_static::lazy_static;
@@ -147,20 +316,54 @@ use lazy_static::lazy_static;
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
+        idt.divide_error.set_handler_fn(divide_error_handler);
+        idt.debug.set_handler_fn(debug_handler);
+        //NMI, double fault and machine check run on their own IST stacks (see gdt.rs), so that
+        //they work even when the kernel stack is broken, e.g. overflowed
+        unsafe {
+            idt.non_maskable_interrupt
+                .set_handler_fn(non_maskable_interrupt_handler)
+                .set_stack_index(gdt::NMI_IST_INDEX);
+        }
         idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.double_fault.set_handler_fn(double_fault_handler);
-        idt.general_protection_fault.set_handler_fn(general_protection_handler);
+        idt.overflow.set_handler_fn(overflow_handler);
+        idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded_handler);
         idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+        idt.device_not_available.set_handler_fn(device_not_available_handler);
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(double_fault_handler)
+                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        }
+        idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+        idt.segment_not_present.set_handler_fn(segment_not_present_handler);
+        idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
+        idt.general_protection_fault.set_handler_fn(general_protection_handler);
+        idt.page_fault.set_handler_fn(page_fault_handler);
+        idt.x87_floating_point.set_handler_fn(x87_floating_point_handler);
+        idt.alignment_check.set_handler_fn(alignment_check_handler);
+        unsafe {
+            idt.machine_check
+                .set_handler_fn(machine_check_handler)
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+        }
+        idt.simd_floating_point.set_handler_fn(simd_floating_point_handler);
+        idt.virtualization.set_handler_fn(virtualization_handler);
+        idt.cp_protection_exception.set_handler_fn(cp_protection_handler);
+        idt.hv_injection_exception.set_handler_fn(hv_injection_handler);
+        idt.vmm_communication_exception.set_handler_fn(vmm_communication_handler);
+        idt.security_exception.set_handler_fn(security_exception_handler);
         idt[InterruptIndex::Timer.as_usize()]
             .set_handler_fn(timer_interrupt_handler); 
         idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Mouse.as_usize()].set_handler_fn(mouse_interrupt_handler);
         idt
     };
 }
 
 //Below function to be called from init() at the bottom of this 
 //this module, to init IDT.
-fn init_idt(){
+pub(crate) fn init_idt(){
     IDT.load();
 }
 
@@ -168,6 +371,26 @@ fn init_idt(){
 pub fn init() {
     init_idt(); //IDT
     init_pics(); //PICS
+    crate::pit::init(); //timer rate
+    //the PS/2 controller is set up while interrupts are still off, so that the handlers do not read its answers
+    if let Err(err) = crate::task::keyboard::init() {
+        serial_println!("PS/2 controller did not answer: {:?}", err);
+    }
+    match crate::task::mouse::init() {
+        Ok(()) => unmask_mouse_irq(),
+        Err(err) => serial_println!("No PS/2 mouse: {:?}", err),
+    }
     x86_64::instructions::interrupts::enable_and_hlt();//enable hardware interrupts. Without handler for timer interrupt, which is on by default, there will be a double fault
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn breakpoint_exception_returns() {
+        init_idt();
+        x86_64::instructions::interrupts::int3(); //must come back here after the report
+    }
+}
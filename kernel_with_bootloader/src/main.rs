@@ -3,29 +3,39 @@
 #![feature(allow_internal_unstable)] //demanded by #[allow_internal_unstable(print_internals, format_args_nl)] in my std.rs
 //below is for x86 interrupts
 #![feature(abi_x86_interrupt)]
+//below is for our in-kernel tests. See testing.rs
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+mod allocator;
+mod console;
+mod gdt;
+mod graphics;
 mod interrupts;
+mod memory;
+mod pit;
+mod ps2;
+mod serial;
 mod smart_pointer_examples;
 pub(crate) mod std;
 pub mod task;
 mod task_example;
+#[cfg(test)]
+mod testing;
 mod writer;
 
 use alloc::{borrow::ToOwned, sync::Arc};
 //use bootloader_api::config::Mapping;
-use writer::FrameBufferWriter;
+use writer::{Color, FrameBufferWriter};
 use x86_64::instructions::hlt;
 
 //let's get heap memory allocation going
+//The #[global_allocator] lives in allocator.rs and is chosen with cargo features
 extern crate alloc;
-use good_memory_allocator::SpinLockedAllocator;
 
-#[global_allocator]
-static ALLOCATOR: SpinLockedAllocator = SpinLockedAllocator::empty();
-
-use bootloader_api::{
-    config::Mapping,
-    info::{MemoryRegion, MemoryRegionKind},
-};
+use bootloader_api::config::Mapping;
+use memory::BootInfoFrameAllocator;
+use x86_64::VirtAddr;
 
 //Use the entry_point macro to register the entry point function: bootloader_api::entry_point!(kernel_main)
 //optionally pass a custom config
@@ -40,7 +50,7 @@ bootloader_api::entry_point!(my_entry_point, config = &BOOTLOADER_CONFIG);
 use lazy_static::lazy_static;
 use spin::Mutex;
 
-use crate::{std::input_str, task::{simple_executor::SimpleExecutor, Task}};
+use crate::{std::input_str, task::{executor::Executor, simple_executor::SimpleExecutor, Task}};
 
 //use lazy static to allow declaration of static without initializing with a constant value
 //Mutex from spin is used for control of threads access.
@@ -50,11 +60,19 @@ lazy_static! {
 }
 
 fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
-    let frame_buffer_info = boot_info.framebuffer.as_mut().unwrap().info();
+    let frame_buffer_info = boot_info.framebuffer.as_ref().map(|framebuffer| framebuffer.info());
 
-    let buffer = boot_info.framebuffer.as_mut().unwrap().buffer_mut();
+    //print! goes to the framebuffer. Without one, e.g. with some BIOS setups, to VGA text mode or the serial port
+    let console = console::init(boot_info.framebuffer.as_mut(), boot_info.physical_memory_offset.into_option());
+    //init picks the font size from the resolution. Uncomment to experience a fixed size and weight
+    //FRAME_BUFFER_WRITER.lock().set_font(writer::RasterHeight::Size24, writer::FontWeight::Bold);
 
-    FRAME_BUFFER_WRITER.lock().init(buffer, frame_buffer_info);
+    match frame_buffer_info {
+        Some(info) => serial_println!("Kernel booted. Framebuffer {}x{}", info.width, info.height),
+        None => serial_println!("Kernel booted without a framebuffer. Console output goes to the {}", console.name()),
+    }
+    //needed to load the kernel symbols in gdb at the right address (see --debug of the host runner)
+    serial_println!("Kernel image offset {:#x}", boot_info.kernel_image_offset);
 
     //println!("Testing testing {} and {} using println!", 1, 4.0 / 2.0); //uncomment for experience.
 
@@ -75,29 +93,76 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     );*/
 
     //let's initialize our global memory allocator
-    let last_memory_region = boot_info.memory_regions.last().unwrap();
+    //Only frames marked Usable in the memory map are used for the heap, which is mapped at memory::HEAP_START
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset.into_option().unwrap());
+    let mut mapper = unsafe { memory::init(physical_memory_offset) };
+    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_regions) };
+
+    memory::init_heap(memory::HEAP_SIZE, &mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
 
-    //get the first bootload memory
-    let mut boot_loader_memory_region = MemoryRegion::empty();
+    unsafe {
+        allocator::init(memory::HEAP_START, memory::HEAP_SIZE);
+    }
+
+    if frame_buffer_info.is_some() {
+        //The console history lives on the heap. Browse it with Shift+PageUp/PageDown
+        FRAME_BUFFER_WRITER.lock().init_scrollback(writer::SCROLLBACK_LINES);
+        //Virtual terminals, switched with Alt+F1..F6. print! goes to the first one
+        FRAME_BUFFER_WRITER.lock().init_terminals();
+        //vt_println!(1, "Hello from virtual terminal 2, press Alt+F2 to see me"); //Uncomment for experience
+        //Draw text in memory and only copy what changed to the framebuffer
+        FRAME_BUFFER_WRITER.lock().init_back_buffer();
+    }
 
-    for memory_region in boot_info.memory_regions.iter() {
-        match memory_region.kind {
-            MemoryRegionKind::Bootloader => {
-                boot_loader_memory_region = *memory_region;
-                break;
+    //A PSF font passed as ramdisk (see KERNEL_FONT in build.rs) draws the chars our built-in font does not have,
+    //e.g. box-drawing or Cyrillic. A font built into the kernel works the same, uncomment for experience:
+    //let font = writer::PsfFont::parse(include_bytes!("../fonts/default8x16.psf")).expect("invalid font");
+    if let Some(ramdisk_addr) = boot_info.ramdisk_addr.into_option() {
+        //the bootloader maps the ramdisk, so it can be used like the embedded bytes
+        let ramdisk = unsafe { core::slice::from_raw_parts(ramdisk_addr as *const u8, boot_info.ramdisk_len as usize) };
+        match writer::PsfFont::parse(ramdisk) {
+            Ok(font) => {
+                serial_println!("Fallback font {}x{} loaded from the ramdisk", font.width(), font.height());
+                FRAME_BUFFER_WRITER.lock().add_fallback_font(alloc::boxed::Box::new(font));
             }
-            _ => continue,
+            Err(error) => serial_println!("Ramdisk is not a PSF font: {:?}", error),
         }
     }
 
-    let physical_memory_offset = boot_info.physical_memory_offset.into_option().unwrap();
-
-    let heap_start = boot_loader_memory_region.end + 0x1 + physical_memory_offset;
-    let heap_size = last_memory_region.end - (boot_loader_memory_region.end + 0x1);
+    //Graphics and text side by side. Uncomment for experience
+    /*
+    use graphics::{Color, Rect, Sprite};
+    let (width, height) = frame_buffer_info.map_or((0, 0), |info| (info.width, info.height));
+    graphics::set_text_region(Rect::new(0, 120, width, height - 120));
+    graphics::draw(|canvas| {
+        canvas.fill_rect(0, 0, width, 120, Color::BLUE);
+        canvas.fill_circle(60, 60, 40, Color::YELLOW);
+        canvas.draw_circle(60, 60, 50, Color::WHITE);
+        canvas.draw_line(120, 10, width as isize - 10, 110, Color::RED);
+        //a 2x2 sprite, half transparent
+        let rgba = [255, 0, 0, 128, 0, 255, 0, 128, 0, 0, 255, 128, 255, 255, 255, 128];
+        canvas.blit(200, 40, &Sprite { width: 2, height: 2, rgba: &rgba });
+    });
+    */
 
-    unsafe {
-        ALLOCATOR.init(heap_start as usize, heap_size as usize);
+    //How fast is printing? Uncomment for experience, then comment out init_back_buffer above and compare
+    /*
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    for i in 0..2000 {
+        println!("Line {} of a long output", i);
     }
+    let cycles = unsafe { core::arch::x86_64::_rdtsc() } - start;
+    serial_println!("Printing 2000 lines took {} cycles", cycles);
+    */
+
+    //Load our GDT and TSS so that double faults, e.g. from a kernel stack overflow,
+    //run on a separate stack instead of resetting the machine. Needed before interrupts::init
+    gdt::init();
+
+    //When built with `cargo test`, run the #[test_case] functions and exit QEMU instead of continuing
+    #[cfg(test)]
+    test_main();
 
     //Let's do a quick test of our heap, using smart pointers
     use alloc::boxed::Box;
@@ -145,22 +210,46 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     futures::join!(thread1, thread2);
     */
     
-    //2. Illustrate a ready-made executor
+    //2. Use the waker-based executor. Pending tasks are only polled again when woken
+    //and the CPU halts while every task is waiting. Uncomment for experience
+    /*
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(run_future()));
+    executor.spawn(Task::new(example_task()));
+    executor.run();
+    */
+
+    //3. Illustrate a ready-made executor
     //Do this for std environment.
 
 
     //For premptive multitasking, we use interrupts
     interrupts::init();
-    let input = input_str!("Ibekwe Prince string :");
-    println!("\nString entered by Ibekwe Prince'{}'",input);
 
-    //Let's experience getting string from keyboard and saving into a variable for use
-    print!("Enter string: ");
-    let input = match input_str() {
-        Some(value) => value,
-        None => "".to_owned()
-    };
-    println!("\nString entered is '{}'", input);
+    //The layout can be chosen when building, e.g. KERNEL_KEYBOARD_LAYOUT=de105 cargo run, or at runtime:
+    //task::keyboard::set_layout(task::keyboard::Layout::Azerty); //Uncomment for experience
+    serial_println!("Keyboard layout {:?}, scancode {:?}", task::keyboard::layout(), task::keyboard::scancode_set());
+
+    //The timer rate can be chosen when building, e.g. KERNEL_TIMER_HZ=100 cargo run
+    serial_println!("Timer at {} Hz", pit::frequency());
+    //How long does printing take? Uncomment for experience
+    /*
+    let start = std::time::Instant::now();
+    for i in 0..100 {
+        println!("Line {}", i);
+    }
+    println!("100 lines in {:?}, up for {:?}", start.elapsed(), std::time::uptime());
+    */
+
+    //Keyboard input is async, so the prompts run as a task on the waker-based executor.
+    //The CPU halts between key presses instead of busy-waiting.
+    let mut executor = Executor::new();
+    executor.spawn(Task::new(task::keyboard::keyboard_task())); //keeps running, for the hotkeys
+    executor.spawn(Task::new(keyboard_session()));
+    executor.spawn(Task::new(task::mouse::pointer_task())); //the pointer needs the back buffer, i.e. a framebuffer
+    //executor.spawn(Task::new(mouse_logger())); //Uncomment to experience mouse events on the serial port, instead of the one above
+    //executor.spawn(Task::new(key_event_logger())); //Uncomment to experience key events on the serial port
+    executor.run();
 
 
     // invoke a breakpoint exception for test
@@ -180,10 +269,90 @@ fn my_entry_point(boot_info: &'static mut bootloader_api::BootInfo) -> ! {
     }
 }
 
+//Let's experience getting string from keyboard and saving into a variable for use
+async fn keyboard_session() {
+    let input = input_str!("Ibekwe Prince string :");
+    println!("\nString entered by Ibekwe Prince'{}'",input);
+
+    print!("Enter string: ");
+    let input = match input_str().await {
+        Some(value) => value,
+        None => "".to_owned()
+    };
+    println!("\nString entered is '{}'", input);
+
+    //Edit the line with the arrow keys, Home/End and Ctrl+A/E/K/U/W, recall it with Up/Down
+    //and complete commands with Tab. Uncomment for experience, Escape to stop
+    /*
+    let mut editor = std::LineEditor::new();
+    editor.set_completer(alloc::boxed::Box::new(|before_cursor: &str| {
+        let word = before_cursor.rsplit(' ').next().unwrap_or("");
+        ["clear", "hello", "help", "history"]
+            .iter()
+            .filter(|command| command.starts_with(word))
+            .map(|command| alloc::string::ToString::to_string(command))
+            .collect()
+    }));
+    while let Some(line) = editor.read_line("> ").await {
+        println!("You entered '{}'", line);
+    }
+    */
+}
+
+//Every key press and release, with the modifiers. Arrow keys, F keys and Ctrl are not lost as with input_str
+#[allow(dead_code)]
+async fn key_event_logger() {
+    use futures_util::stream::StreamExt;
+    let mut events = task::keyboard::subscribe();
+    while let Some(event) = events.next().await {
+        serial_println!("{:?} {:?} {:?} {:?}", event.code, event.state, event.unicode, event.modifiers);
+    }
+}
+
+//Clicks and the wheel of the mouse. Reading the events also moves the pointer
+#[allow(dead_code)]
+async fn mouse_logger() {
+    use futures_util::stream::StreamExt;
+    FRAME_BUFFER_WRITER.lock().set_pointer_visible(true);
+    let mut events = task::mouse::MouseStream::new();
+    let mut buttons = task::mouse::MouseButtons::default();
+    while let Some(event) = events.next().await {
+        if event.buttons != buttons || event.wheel != 0 {
+            serial_println!("{:?} wheel {} at ({}, {})", event.buttons, event.wheel, event.x, event.y);
+            buttons = event.buttons;
+        }
+    }
+}
+
+#[cfg(not(test))]
 #[panic_handler]
 fn panic(_info: &core::panic::PanicInfo) -> ! {
-    println!("{}", _info);
+    //read before printing, which may allocate
+    let heap_exhausted = allocator::last_allocation_failed();
+    //the code that panicked may hold the writer, e.g. the writer itself or a print! in progress.
+    //Locking it again would hang before anything is printed, so then only the serial port gets the message
+    let printed_to_serial = match FRAME_BUFFER_WRITER.try_lock() {
+        Some(mut writer) => {
+            writer.switch_terminal(std::CONSOLE); //the panic message must be seen
+            drop(writer);
+            println_colored!(Color::RED, "{}", _info);
+            console::sink().is_serial() //e.g. booted without a framebuffer
+        }
+        None => false,
+    };
+    if !printed_to_serial {
+        serial_println!("{}", _info); //also to serial so that panics can be seen when running headless
+    }
+    if heap_exhausted {
+        allocator::report_heap_exhaustion(); //we most likely panicked because the heap ran out
+    }
     loop {
         hlt();
     }
 }
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    testing::test_panic_handler(info)
+}
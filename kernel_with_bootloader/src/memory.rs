@@ -0,0 +1,108 @@
+//Paging and physical memory management.
+//The bootloader maps the complete physical memory at physical_memory_offset (see BOOTLOADER_CONFIG
+//in main.rs), which lets us access the page tables through an OffsetPageTable.
+//Ref: https://os.phil-opp.com/paging-implementation/ and https://os.phil-opp.com/heap-allocation/
+
+use bootloader_api::info::{MemoryRegionKind, MemoryRegions};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, OffsetPageTable, Page, PageTable,
+        PageTableFlags, PhysFrame, Size4KiB,
+    },
+    align_up, PhysAddr, VirtAddr,
+};
+
+const FRAME_SIZE: u64 = 4096;
+
+/// Virtual address at which the kernel heap is mapped.
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+
+/// Default size of the kernel heap. Pass a different size to init_heap if required.
+pub const HEAP_SIZE: usize = 16 * 1024 * 1024; // 16 MiB
+
+/// Initializes a new OffsetPageTable.
+///
+/// Unsafe because the caller must guarantee that the complete physical memory is mapped
+/// to virtual memory at the passed physical_memory_offset. Must be called only once
+/// to avoid aliasing &mut references.
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    let level_4_table = active_level_4_table(physical_memory_offset);
+    OffsetPageTable::new(level_4_table, physical_memory_offset)
+}
+
+/// Returns a mutable reference to the active level 4 table.
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr
+}
+
+/// A FrameAllocator that returns usable frames from the bootloader's memory map.
+/// Frames are handed out in order and never reused.
+pub struct BootInfoFrameAllocator {
+    memory_regions: &'static MemoryRegions,
+    region_index: usize, //memory region we are currently allocating from
+    next_address: u64, //lowest physical address that has not been handed out yet
+}
+
+impl BootInfoFrameAllocator {
+    /// Create a FrameAllocator from the passed memory map.
+    ///
+    /// Unsafe because the caller must guarantee that the passed memory map is valid.
+    /// All frames that are marked as Usable in it must really be unused.
+    pub unsafe fn init(memory_regions: &'static MemoryRegions) -> Self {
+        BootInfoFrameAllocator {
+            memory_regions,
+            region_index: 0,
+            next_address: 0,
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        while let Some(region) = self.memory_regions.get(self.region_index) {
+            //skip reserved, bootloader and firmware memory
+            if region.kind == MemoryRegionKind::Usable {
+                let frame_start = align_up(region.start.max(self.next_address), FRAME_SIZE);
+                if frame_start + FRAME_SIZE <= region.end {
+                    self.next_address = frame_start + FRAME_SIZE;
+                    return Some(PhysFrame::containing_address(PhysAddr::new(frame_start)));
+                }
+            }
+            self.region_index += 1;
+        }
+        None //out of physical memory
+    }
+}
+
+/// Maps heap_size bytes of fresh physical frames at HEAP_START.
+/// The global allocator can then be initialized with that range.
+pub fn init_heap(
+    heap_size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page_range = {
+        let heap_start = VirtAddr::new(HEAP_START as u64);
+        let heap_end = heap_start + heap_size as u64 - 1u64;
+        let heap_start_page = Page::containing_address(heap_start);
+        let heap_end_page = Page::containing_address(heap_end);
+        Page::range_inclusive(heap_start_page, heap_end_page)
+    };
+
+    for page in page_range {
+        let frame = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
+
+    Ok(())
+}
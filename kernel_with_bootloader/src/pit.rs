@@ -0,0 +1,130 @@
+//The Programmable Interval Timer (Intel 8253/8254). Channel 0 is wired to IRQ0, i.e. the timer interrupt.
+//Left alone it fires about 18.2 times a second; init programs it to the rate we want, and the timer
+//interrupt handler calls tick so that time is counted. std::time builds Instant and uptime on it.
+//Ref: https://wiki.osdev.org/Programmable_Interval_Timer
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+
+/// The PIT's input clock in Hz. The rate is this divided by a 16 bit divisor.
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+/// Timer interrupts per second, unless KERNEL_TIMER_HZ was set when building, see boot_frequency.
+pub const DEFAULT_FREQUENCY: u32 = 1000;
+
+const CHANNEL_0_PORT: u16 = 0x40;
+const COMMAND_PORT: u16 = 0x43;
+
+/// Channel 0, low then high byte of the divisor, mode 2 (rate generator), binary counting.
+const CHANNEL_0_RATE_GENERATOR: u8 = 0b00_11_010_0;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static NANOS: AtomicU64 = AtomicU64::new(0); //time counted by the ticks so far
+static DIVISOR: AtomicU64 = AtomicU64::new(65536); //what the PIT runs with at boot
+
+//evaluated when building, so that a typo in KERNEL_TIMER_HZ fails the build instead of being ignored
+const BOOT_FREQUENCY: u32 = match option_env!("KERNEL_TIMER_HZ") {
+    None => DEFAULT_FREQUENCY,
+    Some(hz) => match parse_frequency(hz.as_bytes()) {
+        Some(hz) => hz,
+        None => panic!("KERNEL_TIMER_HZ must be a number of Hz from 19 to 1193182"),
+    },
+};
+
+/// Parses a decimal rate that the PIT can run at. A const fn, as str::parse is not const.
+const fn parse_frequency(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() {
+        return None;
+    }
+    let mut hz: u32 = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        if !digits[i].is_ascii_digit() || hz > BASE_FREQUENCY {
+            return None;
+        }
+        hz = hz * 10 + (digits[i] - b'0') as u32;
+        i += 1;
+    }
+    if hz < 19 || hz > BASE_FREQUENCY {
+        return None; //19 Hz is the slowest, with the biggest divisor
+    }
+    Some(hz)
+}
+
+/// The rate at boot: KERNEL_TIMER_HZ when the kernel was built, e.g. `KERNEL_TIMER_HZ=100 cargo run`,
+/// else DEFAULT_FREQUENCY. A value outside of 19 to BASE_FREQUENCY fails the build.
+pub fn boot_frequency() -> u32 {
+    BOOT_FREQUENCY
+}
+
+/// The divisor that comes closest to the given rate. 19 Hz to BASE_FREQUENCY can be reached.
+fn divisor_for(frequency: u32) -> u64 {
+    let frequency = frequency.max(1);
+    let divisor = (BASE_FREQUENCY + frequency / 2) / frequency;
+    divisor.clamp(1, 65536) as u64
+}
+
+/// Nanoseconds between two ticks, rounded.
+fn period_nanos(divisor: u64) -> u64 {
+    (divisor * NANOS_PER_SECOND + BASE_FREQUENCY as u64 / 2) / BASE_FREQUENCY as u64
+}
+
+/// Programs channel 0 to interrupt about frequency times a second, see divisor_for.
+/// Time already counted is kept, so this can also be called while the timer runs.
+pub fn set_frequency(frequency: u32) {
+    let divisor = divisor_for(frequency);
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        DIVISOR.store(divisor, Ordering::Relaxed);
+        let [low, high, ..] = (divisor as u32).to_le_bytes(); //65536 is written as 0, which the PIT reads as 65536
+        unsafe {
+            Port::new(COMMAND_PORT).write(CHANNEL_0_RATE_GENERATOR);
+            let mut channel_0 = Port::new(CHANNEL_0_PORT);
+            channel_0.write(low);
+            channel_0.write(high);
+        }
+    });
+}
+
+/// The actual rate, which differs a little from the one asked for as the divisor is a whole number.
+pub fn frequency() -> u32 {
+    (BASE_FREQUENCY as u64 / DIVISOR.load(Ordering::Relaxed)) as u32
+}
+
+/// Programs the rate at boot. Called by interrupts::init.
+pub(crate) fn init() {
+    set_frequency(boot_frequency());
+}
+
+/// Called by the timer interrupt handler.
+pub(crate) fn tick() {
+    NANOS.fetch_add(period_nanos(DIVISOR.load(Ordering::Relaxed)), Ordering::Relaxed);
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of timer interrupts since interrupts::init.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Nanoseconds since interrupts::init, as precise as the rate: 1 ms at 1000 Hz.
+pub fn uptime_nanos() -> u64 {
+    NANOS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn divisor_is_rounded_and_clamped() {
+        assert_eq!(divisor_for(1000), 1193);
+        assert_eq!(period_nanos(1193), 999_847);
+        assert_eq!(divisor_for(1), 65536);
+        assert_eq!(divisor_for(u32::MAX), 1);
+        assert_eq!(parse_frequency(b"100"), Some(100));
+        assert_eq!(parse_frequency(b"10"), None);
+        assert_eq!(parse_frequency(b"1k"), None);
+    }
+}
@@ -0,0 +1,111 @@
+//The 8042 PS/2 controller, which the keyboard and the mouse (its auxiliary device) are connected to.
+//Both send their bytes through the same data port; the status says whose byte is waiting.
+//Ref: https://wiki.osdev.org/%228042%22_PS/2_Controller
+
+use x86_64::instructions::port::Port;
+
+//reading the command port gives the status
+const DATA_PORT: u16 = 0x60;
+const COMMAND_PORT: u16 = 0x64;
+
+//status bits
+const OUTPUT_FULL: u8 = 1 << 0; //a byte can be read from the data port
+const INPUT_FULL: u8 = 1 << 1; //the controller has not taken the last byte written yet
+const AUX_DATA: u8 = 1 << 5; //the byte to read comes from the mouse, not the keyboard
+
+//controller commands
+pub const DISABLE_KEYBOARD: u8 = 0xAD;
+pub const ENABLE_KEYBOARD: u8 = 0xAE;
+pub const ENABLE_AUX: u8 = 0xA8;
+pub const WRITE_TO_AUX: u8 = 0xD4; //the next byte written to the data port goes to the mouse
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+
+//configuration byte bits
+pub const CONFIG_AUX_INTERRUPT: u8 = 1 << 1;
+pub const CONFIG_AUX_CLOCK_DISABLED: u8 = 1 << 5;
+pub const CONFIG_TRANSLATION: u8 = 1 << 6; //keyboard bytes are translated from scancode set 2 to set 1
+
+/// How often the status is read before giving up, e.g. when there is no controller or device.
+const TIMEOUT_SPINS: usize = 100_000;
+
+/// The controller or a device did not answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+fn status() -> u8 {
+    unsafe { Port::new(COMMAND_PORT).read() }
+}
+
+fn wait_for_write() -> Result<(), Timeout> {
+    (0..TIMEOUT_SPINS).find(|_| status() & INPUT_FULL == 0).map(|_| ()).ok_or(Timeout)
+}
+
+/// Waits for a byte for which from_aux tells whether it should come from the mouse.
+/// Bytes of the other device that get in between are thrown away.
+fn read_from(from_aux: bool) -> Result<u8, Timeout> {
+    for _ in 0..TIMEOUT_SPINS {
+        let status = status();
+        if status & OUTPUT_FULL != 0 {
+            let byte: u8 = unsafe { Port::new(DATA_PORT).read() };
+            if (status & AUX_DATA != 0) == from_aux {
+                return Ok(byte);
+            }
+        }
+    }
+    Err(Timeout)
+}
+
+/// Reads an answer of the controller or the keyboard.
+pub fn read_data() -> Result<u8, Timeout> {
+    read_from(false)
+}
+
+/// Reads an answer of the mouse.
+pub fn read_aux_data() -> Result<u8, Timeout> {
+    read_from(true)
+}
+
+pub fn write_command(command: u8) -> Result<(), Timeout> {
+    wait_for_write()?;
+    unsafe { Port::new(COMMAND_PORT).write(command) };
+    Ok(())
+}
+
+pub fn write_data(byte: u8) -> Result<(), Timeout> {
+    wait_for_write()?;
+    unsafe { Port::new(DATA_PORT).write(byte) };
+    Ok(())
+}
+
+/// Throws away whatever is waiting to be read, e.g. a key pressed during boot.
+fn drain_output() {
+    for _ in 0..TIMEOUT_SPINS {
+        if status() & OUTPUT_FULL == 0 {
+            return;
+        }
+        let _: u8 = unsafe { Port::new(DATA_PORT).read() };
+    }
+}
+
+/// Changes the configuration byte.
+pub fn update_config(update: impl FnOnce(u8) -> u8) -> Result<(), Timeout> {
+    write_command(READ_CONFIG)?;
+    let config = update(read_data()?);
+    write_command(WRITE_CONFIG)?;
+    write_data(config)
+}
+
+/// Talks to the controller with the keyboard port disabled and nothing waiting to be read, so
+/// that no key is taken for an answer, e.g. for the configuration byte, which would then be written
+/// back wrong and could leave the keyboard dead. Interrupts are off meanwhile, as the handlers
+/// read the same data port.
+pub fn without_keyboard<R, E: From<Timeout>>(f: impl FnOnce() -> Result<R, E>) -> Result<R, E> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        write_command(DISABLE_KEYBOARD)?;
+        drain_output();
+        let result = f();
+        write_command(ENABLE_KEYBOARD)?;
+        result
+    })
+}
@@ -0,0 +1,38 @@
+//Serial port console. Unlike the framebuffer, output written here can be captured on the host,
+//e.g. when QEMU runs with -serial stdio (see src/main.rs of the host runner).
+//Ref: https://os.phil-opp.com/testing/#serial-port
+
+use lazy_static::lazy_static;
+use spin::Mutex;
+use uart_16550::SerialPort;
+
+/// I/O port of the first serial interface (COM1).
+const COM1_PORT: u16 = 0x3F8;
+
+lazy_static! {
+    pub static ref SERIAL1: Mutex<SerialPort> = {
+        let mut serial_port = unsafe { SerialPort::new(COM1_PORT) };
+        serial_port.init();
+        Mutex::new(serial_port)
+    };
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        write!($crate::serial::SERIAL1.lock(), "{}", format_args!($($arg)*)).unwrap();
+    }};
+}
+
+#[macro_export]
+#[allow_internal_unstable(print_internals, format_args_nl)]
+macro_rules! serial_println {
+    () => {
+        $crate::serial_print!("\n")
+    };
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        write!($crate::serial::SERIAL1.lock(), "{}", format_args_nl!($($arg)*)).unwrap();
+    }};
+}
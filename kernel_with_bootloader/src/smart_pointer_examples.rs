@@ -98,4 +98,28 @@ pub fn print_tree(tree: Rc<RefCell<Node>>) {
 //A quick look at Arc and Mutex where there are thread safety concerns
 //See task_example.rs
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn rc_clones_share_the_value() {
+        let y = Rc::new("shared".to_string());
+        let y1 = y.clone();
+        assert_eq!(Rc::strong_count(&y), 2);
+        assert!(Rc::ptr_eq(&y, &y1));
+        drop(y1);
+        assert_eq!(Rc::strong_count(&y), 1);
+    }
 
+    #[test_case]
+    fn add_child_links_parent() {
+        let root = create_tree();
+        add_child(&root);
+        assert_eq!(root.borrow().children.len(), 2);
+        for child in &root.borrow().children {
+            let parent = child.borrow().parent.as_ref().unwrap().upgrade().unwrap();
+            assert!(Rc::ptr_eq(&parent, &root));
+        }
+    }
+}
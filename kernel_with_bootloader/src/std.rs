@@ -1,15 +1,25 @@
 use alloc::string::{String, ToString};
 
-use crate::interrupts::KEY_PRESSED;
+use futures_util::stream::StreamExt;
+use pc_keyboard::DecodedKey;
 
+use crate::task::keyboard::KeyStream;
+
+mod line_editor;
 pub(crate) mod prelude;
+pub mod time;
+
+#[allow(unused_imports)] //for the programs we write
+pub use line_editor::{read_line, Completer, LineEditor};
+
+/// The virtual terminal that print!, println! and input_str use. Alt+F1 shows it.
+pub const CONSOLE: usize = 0;
 
 #[macro_export]
 macro_rules! print {
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        write!($crate::FRAME_BUFFER_WRITER.lock(), "{}", format_args!($($arg)*)).unwrap();
-    }};
+    ($($arg:tt)*) => {
+        $crate::vt_print!($crate::std::CONSOLE, $($arg)*)
+    };
 }
 
 #[macro_export]
@@ -18,19 +28,58 @@ macro_rules! println {
     () => {
         $crate::print!("\n")
     };
-    ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        write!($crate::FRAME_BUFFER_WRITER.lock(), "{}", format_args_nl!($($arg)*)).unwrap();
-    }};
+    ($($arg:tt)*) => {
+        $crate::vt_println!($crate::std::CONSOLE, $($arg)*)
+    };
+}
+
+/// Like print!, but to the given virtual terminal, e.g. vt_print!(2, "{} done", name).
+/// It shows up when switching to that terminal with Alt+F1..F6.
+#[macro_export]
+macro_rules! vt_print {
+    ($terminal:expr, $($arg:tt)*) => {
+        $crate::console::_print($terminal, format_args!($($arg)*))
+    };
 }
 
 #[macro_export]
 #[allow_internal_unstable(print_internals, format_args_nl)]
-macro_rules! input_str {
+macro_rules! vt_println {
+    ($terminal:expr) => {
+        $crate::vt_print!($terminal, "\n")
+    };
+    ($terminal:expr, $($arg:tt)*) => {
+        $crate::console::_print($terminal, format_args_nl!($($arg)*))
+    };
+}
+
+/// Like print!, but in the given foreground color. The previous colors are restored afterwards.
+/// e.g. print_colored!(Color::RED, "{} failed", name)
+#[macro_export]
+macro_rules! print_colored {
+    ($color:expr, $($arg:tt)*) => {
+        $crate::console::_print_colored($crate::std::CONSOLE, $color, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+#[allow_internal_unstable(print_internals, format_args_nl)]
+macro_rules! println_colored {
+    ($color:expr) => {
+        $crate::print!("\n")
+    };
+    ($color:expr, $($arg:tt)*) => {
+        $crate::console::_print_colored($crate::std::CONSOLE, $color, format_args_nl!($($arg)*))
+    };
+}
 
+#[macro_export]
+#[allow_internal_unstable(print_internals, format_args_nl)]
+macro_rules! input_str {
+    //input_str is async, so this macro can only be used inside an async fn or block
     ($prompt:expr) => {{
-        print!("{}",$prompt);
-        match input_str() {
+        $crate::print!("{}",$prompt);
+        match $crate::std::input_str().await {
             Some(value) => value,
             None => "".to_owned(),
         }
@@ -39,42 +88,62 @@ macro_rules! input_str {
     };
 }
 
-pub fn input_str() -> Option<String> {
+/// Like input_str!, but prompts on and reads from the given virtual terminal.
+#[macro_export]
+macro_rules! vt_input_str {
+    ($terminal:expr, $prompt:expr) => {{
+        let terminal = $terminal;
+        $crate::vt_print!(terminal, "{}", $prompt);
+        match $crate::std::vt_input_str(terminal).await {
+            Some(value) => value,
+            None => "".to_owned(),
+        }
+    }};
+}
+
+/// Reads a line from the keyboard. Returns None if escape is pressed.
+///
+/// The task sleeps while no key is pressed instead of spinning, so it must be run by an executor
+/// (see task::executor) after interrupts::init() has been called.
+pub async fn input_str() -> Option<String> {
+    vt_input_str(CONSOLE).await
+}
+
+/// Like input_str, but reads the keys typed while the given virtual terminal is shown
+/// and echoes them there.
+pub async fn vt_input_str(terminal: usize) -> Option<String> {
     let mut input: String = "".to_string();
     let mut input_counter:u32 = 0; //keep a count so that backspaced induced pop is not allowed beyond the count
-    let mut character = *KEY_PRESSED.lock();
+    let mut keys = KeyStream::new(terminal);
 
-    while character != Some('\u{000D}') && character != Some('\u{000A}'){//Test for all three breakout conditions
+    while let Some(key) = keys.next().await {
+        let character = match key {
+            DecodedKey::Unicode(character) => character,
+            DecodedKey::RawKey(key) => {
+                vt_print!(terminal, "{:?}", key);
+                continue;
+            }
+        };
         match character {
-            None => {
-                //do nothing
-            },
-            Some ('\u{0008}') => {//backspace pressed
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that backspace effect is not repeated
+            '\u{0008}' => {//backspace pressed
                 if input_counter > 0 {
-                    print!("{}", character.unwrap());//visually move backwards
+                    vt_print!(terminal, "{}", character);//visually move backwards
                     input.pop(); //pop from input
                     input_counter -=1;
                 }
-                
             },
-            Some ('\u{000A}') => { //escape pressed. Return None from the function immediately
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that effect is not repeated
+            '\u{001B}' => { //escape pressed. Return None from the function immediately
                 return None;
             },
-            Some('\u{000D}') => {//Simply breakout of loop if carriage return is pressed.
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED so that effect is not repeated
+            '\u{000D}' | '\u{000A}' => {//Simply breakout of loop if carriage return or newline is pressed.
                 break;
             },
             _ => {//Every other unicode key sent, push to input
-                let char_received = character.unwrap().clone();//clone it for keep
-                print!("{}", &char_received);//show char received on console
-                input.push(char_received); //move the character to input
+                vt_print!(terminal, "{}", character);//show char received on console
+                input.push(character); //move the character to input
                 input_counter+=1; //keep a count so that backspaced induced pop is not allowed beyond the count
-                *KEY_PRESSED.lock() = None; //clear global KEY_PRESSED after cloning
             }
         }
-        character = *KEY_PRESSED.lock(); //read again as long as we have not broken out.
     };
     Some(input) //return the final input string
 }
\ No newline at end of file
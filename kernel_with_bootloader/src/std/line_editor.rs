@@ -0,0 +1,346 @@
+//Line editing for reading commands from the keyboard, like readline or the shells do.
+//Keys: Left/Right, Home/End (also Ctrl+A/Ctrl+E), Backspace, Delete, Ctrl+K (cut to the end),
+//Ctrl+U (cut to the start), Ctrl+W (cut the word before the cursor), Up/Down (history) and Tab (completion).
+//Escape and Ctrl+C cancel.
+//The line is redrawn with ANSI escape sequences (CUB and EL), so it should fit on one line of the screen.
+//Ref: https://en.wikipedia.org/wiki/GNU_Readline#Emacs_keyboard_shortcuts
+#![allow(dead_code)] //an API for the programs we write, see keyboard_session in main.rs for an example
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+use futures_util::stream::StreamExt;
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::task::keyboard::KeyStream;
+use crate::vt_print;
+
+/// Number of lines kept by a LineEditor for Up/Down, unless set with set_max_history.
+pub const DEFAULT_MAX_HISTORY: usize = 100;
+
+/// Tab completion. Gets the text before the cursor and returns the possible completions of its last
+/// word, i.e. of the chars after the last space. E.g. for "he" the commands "help" and "hello".
+pub type Completer = Box<dyn Fn(&str) -> Vec<String>>;
+
+//what Ctrl plus a letter gives with HandleControl::MapLettersToUnicode
+const CTRL_A: char = '\u{0001}';
+const CTRL_C: char = '\u{0003}';
+const CTRL_E: char = '\u{0005}';
+const CTRL_K: char = '\u{000B}';
+const CTRL_U: char = '\u{0015}';
+const CTRL_W: char = '\u{0017}';
+
+/// Reads lines with editing, history and completion. Keep it around for the history to be useful.
+pub struct LineEditor {
+    terminal: usize,
+    history: Vec<String>,
+    max_history: usize,
+    completer: Option<Completer>,
+}
+
+/// The line being edited and what of it is on the screen.
+struct Line {
+    terminal: usize,
+    chars: Vec<char>,
+    cursor: usize,
+    shown_cursor: usize, //where the cursor is on the screen, relative to the end of the prompt
+}
+
+impl Line {
+    /// Shows the line after it has changed. Moves back to the end of the prompt, prints the line
+    /// over the old one, erases what is left of that and moves to the cursor.
+    fn redraw(&mut self) {
+        if self.shown_cursor > 0 {
+            vt_print!(self.terminal, "\x1b[{}D", self.shown_cursor);
+        }
+        let text: String = self.chars.iter().collect();
+        vt_print!(self.terminal, "{}\x1b[K", text);
+        self.shown_cursor = self.chars.len();
+        self.move_cursor();
+    }
+
+    /// Moves the cursor on the screen to self.cursor, when only that has changed.
+    fn move_cursor(&mut self) {
+        if self.cursor < self.shown_cursor {
+            vt_print!(self.terminal, "\x1b[{}D", self.shown_cursor - self.cursor);
+        } else if self.cursor > self.shown_cursor {
+            vt_print!(self.terminal, "\x1b[{}C", self.cursor - self.shown_cursor);
+        }
+        self.shown_cursor = self.cursor;
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+        self.redraw();
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.chars.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+        self.redraw();
+    }
+
+    /// Removes the chars from start up to (excluding) end, and puts the cursor at start.
+    fn cut(&mut self, start: usize, end: usize) {
+        if start < end {
+            self.chars.drain(start..end);
+            self.cursor = start;
+            self.redraw();
+        }
+    }
+
+    /// Start of the word before the cursor, after skipping the spaces before it.
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && self.chars[start - 1] == ' ' {
+            start -= 1;
+        }
+        while start > 0 && self.chars[start - 1] != ' ' {
+            start -= 1;
+        }
+        start
+    }
+}
+
+/// Where Up/Down are in the history while a line is read. Keeps the line being typed, the draft,
+/// while older lines are shown, so that Down past the newest one brings it back.
+struct HistoryPosition {
+    index: usize, //history.len() is the draft
+    draft: String,
+}
+
+impl HistoryPosition {
+    fn new(history: &[String]) -> Self {
+        HistoryPosition { index: history.len(), draft: String::new() }
+    }
+
+    /// The line to show for Up, None at the oldest one. line is what is being edited now.
+    fn up<'a>(&mut self, history: &'a [String], line: &str) -> Option<&'a str> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == history.len() {
+            self.draft = line.into();
+        }
+        self.index -= 1;
+        Some(&history[self.index])
+    }
+
+    /// The line to show for Down, None when already back at the draft.
+    fn down<'a>(&'a mut self, history: &'a [String]) -> Option<&'a str> {
+        if self.index >= history.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(history.get(self.index).unwrap_or(&self.draft))
+    }
+}
+
+/// Number of chars at the start that all candidates have in common.
+fn common_prefix_len(candidates: &[String]) -> usize {
+    let Some((first, others)) = candidates.split_first() else {
+        return 0;
+    };
+    others.iter().fold(first.chars().count(), |common, candidate| {
+        first.chars().zip(candidate.chars()).take(common).take_while(|(a, b)| a == b).count()
+    })
+}
+
+impl LineEditor {
+    /// A line editor for the console, i.e. virtual terminal 0. See on_terminal for the others.
+    pub fn new() -> Self {
+        Self::on_terminal(crate::std::CONSOLE)
+    }
+
+    /// A line editor that reads the keys typed while the given virtual terminal is shown, and echoes there.
+    pub fn on_terminal(terminal: usize) -> Self {
+        LineEditor {
+            terminal,
+            history: Vec::new(),
+            max_history: DEFAULT_MAX_HISTORY,
+            completer: None,
+        }
+    }
+
+    /// Sets what Tab does, see Completer.
+    pub fn set_completer(&mut self, completer: Completer) {
+        self.completer = Some(completer);
+    }
+
+    pub fn set_max_history(&mut self, max_history: usize) {
+        self.max_history = max_history;
+        self.trim_history();
+    }
+
+    /// The lines entered so far, oldest first.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    fn trim_history(&mut self) {
+        if self.history.len() > self.max_history {
+            let excess = self.history.len() - self.max_history;
+            self.history.drain(..excess);
+        }
+    }
+
+    /// Shows the prompt and reads a line. Returns None if Escape or Ctrl+C is pressed.
+    /// Non-empty lines are added to the history.
+    pub async fn read_line(&mut self, prompt: &str) -> Option<String> {
+        vt_print!(self.terminal, "{}", prompt);
+        let mut line = Line {
+            terminal: self.terminal,
+            chars: Vec::new(),
+            cursor: 0,
+            shown_cursor: 0,
+        };
+        let mut history = HistoryPosition::new(&self.history);
+        let mut keys = KeyStream::new(self.terminal);
+
+        while let Some(key) = keys.next().await {
+            match key {
+                DecodedKey::Unicode('\n' | '\r') => break,
+                DecodedKey::Unicode('\u{001B}') => {
+                    vt_print!(self.terminal, "\n");
+                    return None;
+                }
+                DecodedKey::Unicode(CTRL_C) => {
+                    vt_print!(self.terminal, "^C\n");
+                    return None;
+                }
+                DecodedKey::RawKey(KeyCode::ArrowLeft) if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    line.move_cursor();
+                }
+                DecodedKey::RawKey(KeyCode::ArrowRight) if line.cursor < line.chars.len() => {
+                    line.cursor += 1;
+                    line.move_cursor();
+                }
+                DecodedKey::RawKey(KeyCode::Home) | DecodedKey::Unicode(CTRL_A) => {
+                    line.cursor = 0;
+                    line.move_cursor();
+                }
+                DecodedKey::RawKey(KeyCode::End) | DecodedKey::Unicode(CTRL_E) => {
+                    line.cursor = line.chars.len();
+                    line.move_cursor();
+                }
+                DecodedKey::Unicode('\u{0008}') if line.cursor > 0 => line.cut(line.cursor - 1, line.cursor),
+                DecodedKey::RawKey(KeyCode::Delete) | DecodedKey::Unicode('\u{007F}') => {
+                    line.cut(line.cursor, (line.cursor + 1).min(line.chars.len()));
+                }
+                DecodedKey::Unicode(CTRL_K) => {
+                    let cursor = line.cursor;
+                    line.cut(cursor, line.chars.len());
+                }
+                DecodedKey::Unicode(CTRL_U) => line.cut(0, line.cursor),
+                DecodedKey::Unicode(CTRL_W) => line.cut(line.word_start(), line.cursor),
+                DecodedKey::RawKey(KeyCode::ArrowUp) => {
+                    let text: String = line.chars.iter().collect();
+                    if let Some(entry) = history.up(&self.history, &text) {
+                        line.set(entry);
+                    }
+                }
+                DecodedKey::RawKey(KeyCode::ArrowDown) => {
+                    if let Some(entry) = history.down(&self.history) {
+                        line.set(entry);
+                    }
+                }
+                DecodedKey::Unicode('\t') => self.complete(&mut line, prompt),
+                DecodedKey::Unicode(c) if !c.is_control() => {
+                    let mut buffer = [0; 4];
+                    line.insert(c.encode_utf8(&mut buffer));
+                }
+                _ => {} //e.g. F keys, or Left at the start of the line
+            }
+        }
+        vt_print!(self.terminal, "\n");
+
+        let text: String = line.chars.iter().collect();
+        if !text.is_empty() && self.history.last() != Some(&text) {
+            self.history.push(text.clone());
+            self.trim_history();
+        }
+        Some(text)
+    }
+
+    /// Completes the word before the cursor as far as all candidates agree. If that adds nothing,
+    /// lists the candidates under the line and shows the prompt and line again.
+    fn complete(&self, line: &mut Line, prompt: &str) {
+        let Some(completer) = self.completer.as_ref() else {
+            return;
+        };
+        let before_cursor: String = line.chars[..line.cursor].iter().collect();
+        let candidates = completer(&before_cursor);
+        let word_len = before_cursor.chars().rev().take_while(|&c| c != ' ').count();
+        let word_start = line.cursor - word_len;
+        match candidates.as_slice() {
+            [] => {}
+            [candidate] => {
+                line.cut(word_start, line.cursor);
+                line.insert(candidate);
+                line.insert(" ");
+            }
+            [first, ..] => {
+                let common = common_prefix_len(&candidates);
+                if common > word_len {
+                    let prefix: String = first.chars().take(common).collect();
+                    line.cut(word_start, line.cursor);
+                    line.insert(&prefix);
+                } else {
+                    vt_print!(line.terminal, "\n");
+                    for candidate in &candidates {
+                        vt_print!(line.terminal, "{}  ", candidate);
+                    }
+                    let text: String = line.chars.iter().collect();
+                    vt_print!(line.terminal, "\n{}{}", prompt, text);
+                    line.shown_cursor = line.chars.len();
+                    line.move_cursor();
+                }
+            }
+        }
+    }
+}
+
+/// Reads a line with the editing keys of LineEditor, but without history or completion.
+/// Returns None if Escape or Ctrl+C is pressed.
+pub async fn read_line(prompt: &str) -> Option<String> {
+    LineEditor::new().read_line(prompt).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn ctrl_w_cuts_back_to_the_previous_word() {
+        let chars: Vec<char> = "echo hello  ".chars().collect();
+        let mut line = Line { terminal: 0, cursor: chars.len(), shown_cursor: 0, chars };
+        assert_eq!(line.word_start(), 5);
+        line.cursor = 4;
+        assert_eq!(line.word_start(), 0);
+    }
+
+    #[test_case]
+    fn completion_extends_to_the_common_prefix() {
+        let candidates = |names: &[&str]| names.iter().map(|&name| String::from(name)).collect::<Vec<_>>();
+        assert_eq!(common_prefix_len(&candidates(&["hello", "help", "helm"])), 3);
+        assert_eq!(common_prefix_len(&candidates(&["he", "hello"])), 2);
+        assert_eq!(common_prefix_len(&candidates(&["äpfel", "äpfelsaft"])), 5); //chars, not bytes
+        assert_eq!(common_prefix_len(&candidates(&["cat", "ls"])), 0);
+    }
+
+    #[test_case]
+    fn history_up_and_down_restore_the_draft() {
+        let history = [String::from("one"), String::from("two")];
+        let mut position = HistoryPosition::new(&history);
+        assert_eq!(position.down(&history), None);
+        assert_eq!(position.up(&history, "dra"), Some("two"));
+        assert_eq!(position.up(&history, "two"), Some("one"));
+        assert_eq!(position.up(&history, "one"), None);
+        assert_eq!(position.down(&history), Some("two"));
+        assert_eq!(position.down(&history), Some("dra"));
+        assert_eq!(position.down(&history), None);
+    }
+}
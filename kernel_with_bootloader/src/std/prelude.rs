@@ -1,5 +1,10 @@
 #![allow(unused_imports)]
 pub use crate::println;
+pub use crate::{print_colored, println_colored};
+pub use crate::{vt_print, vt_println};
+pub use crate::writer::Color;
+pub use crate::std::{read_line, LineEditor};
+pub use crate::std::time::{uptime, Duration, Instant};
 //let import ahead of time, our data structures that involve heap
 //as if they are all standard to our offerings.
 pub use alloc::string::String;
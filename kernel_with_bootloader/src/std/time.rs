@@ -0,0 +1,87 @@
+//Measuring time, like std::time. Time is counted by the timer interrupt (see pit.rs), so it only
+//advances once interrupts::init has run, and only as precisely as the timer rate.
+//e.g. let start = Instant::now(); work(); println!("took {:?}", start.elapsed());
+#![allow(dead_code)] //an API for the programs we write
+
+use core::ops::{Add, Sub};
+pub use core::time::Duration;
+
+/// Number of timer interrupts so far, e.g. for code that counts in ticks rather than time.
+#[allow(unused_imports)]
+pub use crate::pit::ticks;
+
+/// A point in time since boot, which never goes backwards. Only useful compared to another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    nanos: u64, //since interrupts::init
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant { nanos: crate::pit::uptime_nanos() }
+    }
+
+    /// The time from earlier to self, zero if earlier is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant { nanos: self.nanos.checked_add(nanos)? })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant { nanos: self.nanos.checked_sub(nanos)? })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Panics on overflow, like std.
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Time since interrupts::init, i.e. since the timer started counting.
+pub fn uptime() -> Duration {
+    Duration::from_nanos(crate::pit::uptime_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn instants_are_ordered_by_duration() {
+        let start = Instant { nanos: 1_000 };
+        let later = start + Duration::from_micros(5);
+        assert!(later > start);
+        assert_eq!(later - start, Duration::from_nanos(5_000));
+        assert_eq!(start.duration_since(later), Duration::ZERO);
+        assert_eq!(start.checked_sub(Duration::from_secs(1)), None);
+    }
+}
@@ -1,20 +1,38 @@
+pub mod executor;
+pub mod keyboard;
+pub mod mouse;
 pub mod simple_executor;
 
 use core::{future::Future, pin::Pin};
+use core::sync::atomic::{AtomicU64, Ordering};
 use alloc::boxed::Box;
 
 pub struct Task {
+    id: TaskId,
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
 impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
         Task {
+            id: TaskId::new(),
             future: Box::pin(future),
         }
     }
 }
 
+/// Unique id of a task. The waker-based executor uses it to know which task to poll again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct TaskId(u64);
+
+impl TaskId {
+    fn new() -> Self {
+        //a global counter guarantees that no two tasks ever share an id
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 use core::task::{Context, Poll};
 
 impl Task {
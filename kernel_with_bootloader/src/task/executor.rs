@@ -0,0 +1,155 @@
+//Waker-based executor. Unlike SimpleExecutor, a pending task is only polled again
+//after its waker has been called, and the CPU is halted while there is nothing to do.
+//Ref: https://os.phil-opp.com/async-await/#executor-with-waker-support
+
+use super::{Task, TaskId};
+use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::{
+    fmt::Write,
+    task::{Context, Poll, Waker},
+};
+use crossbeam_queue::ArrayQueue;
+
+/// Maximum number of task ids that can wait in the wake queue at the same time.
+const TASK_QUEUE_CAPACITY: usize = 100;
+
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    //lock-free so that wakers can push into it from interrupt handlers
+    task_queue: Arc<ArrayQueue<TaskId>>,
+    //cache wakers so that they are not recreated on every poll
+    waker_cache: BTreeMap<TaskId, Waker>,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            tasks: BTreeMap::new(),
+            task_queue: Arc::new(ArrayQueue::new(TASK_QUEUE_CAPACITY)),
+            waker_cache: BTreeMap::new(),
+        }
+    }
+
+    pub fn spawn(&mut self, task: Task) {
+        let task_id = task.id;
+        if self.tasks.insert(task.id, task).is_some() {
+            panic!("task with same ID already in tasks");
+        }
+        self.task_queue.push(task_id).expect("queue full");
+    }
+
+    /// Polls every task that has been woken since the last call.
+    fn run_ready_tasks(&mut self) {
+        //destructure self to avoid borrow checker errors in the closure below
+        let Self {
+            tasks,
+            task_queue,
+            waker_cache,
+        } = self;
+
+        while let Some(task_id) = task_queue.pop() {
+            let task = match tasks.get_mut(&task_id) {
+                Some(task) => task,
+                None => continue, // task no longer exists
+            };
+            let waker = waker_cache
+                .entry(task_id)
+                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+            let mut context = Context::from_waker(waker);
+            match task.poll(&mut context) {
+                Poll::Ready(()) => {
+                    // task done -> remove it and its cached waker
+                    tasks.remove(&task_id);
+                    waker_cache.remove(&task_id);
+                }
+                Poll::Pending => {}
+            }
+        }
+    }
+
+    /// Runs tasks until all of them have completed.
+    pub fn run(&mut self) {
+        loop {
+            self.run_ready_tasks();
+            if self.tasks.is_empty() {
+                break; //do not hlt when there is nothing left to wait for
+            }
+            self.sleep_if_idle();
+        }
+    }
+
+    /// Runs tasks forever. Use this when tasks are expected to never complete.
+    pub fn run_forever(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+
+    fn sleep_if_idle(&self) {
+        use x86_64::instructions::interrupts::{self, enable_and_hlt};
+
+        //disable interrupts before checking the queue, else an interrupt that wakes
+        //a task between the check and hlt() would be missed until the next interrupt
+        interrupts::disable();
+        if self.task_queue.is_empty() {
+            enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+}
+
+struct TaskWaker {
+    task_id: TaskId,
+    task_queue: Arc<ArrayQueue<TaskId>>,
+}
+
+impl TaskWaker {
+    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
+        Waker::from(Arc::new(TaskWaker {
+            task_id,
+            task_queue,
+        }))
+    }
+
+    /// Called from interrupt handlers too, so it must not panic or block. Each wake pushes the id, so a
+    /// full queue mostly holds ids that are already in it more than once, e.g. of a task woken by every
+    /// key press. The wake is dropped then, and logged in case the task was not queued yet.
+    fn wake_task(&self) {
+        if self.task_queue.push(self.task_id).is_err() {
+            //try_lock, as the interrupted code may hold the serial port
+            if let Some(mut serial) = crate::serial::SERIAL1.try_lock() {
+                let _ = writeln!(serial, "task_queue full, dropped a wake of {:?}", self.task_id);
+            }
+        }
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_task();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wake_task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task_example::{run_modify_data, SharedData};
+    use spin::Mutex;
+
+    #[test_case]
+    fn run_completes_all_tasks() {
+        let data = Arc::new(Mutex::new(SharedData { value: 30 }));
+        let mut executor = Executor::new();
+        executor.spawn(Task::new(run_modify_data(data.clone())));
+        executor.spawn(Task::new(run_modify_data(data.clone())));
+        executor.run();
+        assert!(executor.tasks.is_empty());
+        assert_eq!(data.lock().value, 50);
+    }
+}
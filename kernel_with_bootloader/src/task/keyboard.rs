@@ -0,0 +1,316 @@
+//Async keyboard input. The keyboard interrupt handler only pushes raw scancodes into a
+//fixed-capacity lock-free queue; decoding happens in keyboard_task, which main keeps running.
+//It handles the hotkeys (Shift+PageUp/PageDown, Alt+F1..F6) and hands every other key to the virtual terminal
+//that is shown when it is pressed, so every terminal has its own input queue that KeyStream reads.
+//Programs that want key presses and releases with the modifiers subscribe to KeyEvents instead (see event).
+//Ref: https://os.phil-opp.com/async-await/#async-keyboard-input
+
+mod event;
+mod layout;
+
+use conquer_once::spin::OnceCell;
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+use lazy_static::lazy_static;
+use crate::ps2;
+use crate::writer::TERMINAL_COUNT;
+use layout::Decoder;
+use pc_keyboard::{DecodedKey, HandleControl, KeyCode};
+use spin::Mutex;
+
+/// Maximum number of scancodes kept while no task is reading them. Further scancodes are dropped.
+const SCANCODE_QUEUE_CAPACITY: usize = 100;
+
+/// Maximum number of keys kept for each virtual terminal. Further keys are dropped.
+const KEY_QUEUE_CAPACITY: usize = 100;
+
+static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+static KEY_QUEUES: OnceCell<[ArrayQueue<DecodedKey>; TERMINAL_COUNT]> = OnceCell::uninit();
+static KEY_WAKERS: [AtomicWaker; TERMINAL_COUNT] = [const { AtomicWaker::new() }; TERMINAL_COUNT];
+
+/// Called by the keyboard interrupt handler.
+///
+/// Must not block or allocate.
+pub(crate) fn add_scancode(scancode: u8) {
+    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
+        //a full queue drops the scancode rather than blocking inside the interrupt handler
+        if queue.push(scancode).is_ok() {
+            WAKER.wake();
+        }
+    }
+    //queue not yet initialized means nobody is listening, so the scancode is simply dropped
+}
+
+/// Stream of raw scancodes from the keyboard. Only one task should read from it at a time,
+/// as only the last registered waker is woken.
+pub struct ScancodeStream {
+    _private: (),
+}
+
+impl ScancodeStream {
+    pub fn new() -> Self {
+        //the queue is shared by all streams, so only the first call allocates it
+        let _ = SCANCODE_QUEUE.try_init_once(|| ArrayQueue::new(SCANCODE_QUEUE_CAPACITY));
+        ScancodeStream { _private: () }
+    }
+}
+
+impl Stream for ScancodeStream {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let queue = SCANCODE_QUEUE
+            .try_get()
+            .expect("scancode queue not initialized");
+
+        //fast path: avoid registering the waker if a scancode is already there
+        if let Some(scancode) = queue.pop() {
+            return Poll::Ready(Some(scancode));
+        }
+
+        WAKER.register(cx.waker());
+        //check again, as the interrupt handler might have pushed before the waker was registered
+        match queue.pop() {
+            Some(scancode) => {
+                WAKER.take();
+                Poll::Ready(Some(scancode))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+pub use event::{subscribe, KeyEvent, KeyEventStream, Modifiers};
+pub use layout::{Layout, ScancodeSet};
+
+lazy_static! {
+    //Decoder state (e.g. shift being held) must survive across reads, hence a global.
+    //Ctrl+letter gives the control chars (Ctrl+A is U+0001), which the line editor uses
+    static ref KEYBOARD: Mutex<Decoder> = Mutex::new(
+        Decoder::new(layout::boot_layout(), layout::boot_scancode_set(), HandleControl::MapLettersToUnicode)
+    );
+}
+
+/// Switches the keyboard layout, e.g. set_layout(Layout::De105). The one at boot can be chosen with
+/// KERNEL_KEYBOARD_LAYOUT when building, see layout::boot_layout.
+pub fn set_layout(layout: Layout) {
+    KEYBOARD.lock().set_layout(layout);
+}
+
+pub fn layout() -> Layout {
+    KEYBOARD.lock().layout()
+}
+
+/// Switches the scancode set the keyboard bytes are decoded with, and has the PS/2 controller
+/// translate them to set 1 or not to match. The one at boot can be chosen with KERNEL_SCANCODE_SET
+/// when building. Nothing changes if the controller does not answer.
+pub fn set_scancode_set(scancode_set: ScancodeSet) -> Result<(), ps2::Timeout> {
+    program_scancode_set(scancode_set)?;
+    KEYBOARD.lock().set_scancode_set(scancode_set);
+    Ok(())
+}
+
+/// The keyboard sends set 2, so the controller's translation gives set 1, see ScancodeSet.
+fn program_scancode_set(scancode_set: ScancodeSet) -> Result<(), ps2::Timeout> {
+    ps2::without_keyboard(|| {
+        ps2::update_config(|config| match scancode_set {
+            ScancodeSet::Set1 => config | ps2::CONFIG_TRANSLATION,
+            ScancodeSet::Set2 => config & !ps2::CONFIG_TRANSLATION,
+        })
+    })
+}
+
+/// Has the controller send the scancode set chosen at boot. Called by interrupts::init.
+pub(crate) fn init() -> Result<(), ps2::Timeout> {
+    program_scancode_set(scancode_set())
+}
+
+pub fn scancode_set() -> ScancodeSet {
+    KEYBOARD.lock().scancode_set()
+}
+
+/// MapLettersToUnicode, the default, makes Ctrl+A..Ctrl+Z give U+0001..U+001A, e.g. Ctrl+C U+0003.
+/// Ignore makes them give the letters.
+pub fn set_handle_control(handle_control: HandleControl) {
+    KEYBOARD.lock().set_handle_control(handle_control);
+}
+
+//pc_keyboard tracks the modifiers internally but does not expose them, so we track them too,
+//e.g. for Shift+PageUp/PageDown and Alt+F1..F6
+static MODIFIERS: Mutex<Modifiers> = Mutex::new(Modifiers::new());
+
+/// Keys that the kernel handles itself, whichever task reads the keyboard or whether any does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hotkey {
+    ScrollbackPageUp,   //Shift+PageUp
+    ScrollbackPageDown, //Shift+PageDown
+    SwitchTerminal(usize), //Alt+F1..F6
+}
+
+impl Hotkey {
+    fn run(self) {
+        match self {
+            Hotkey::ScrollbackPageUp => crate::FRAME_BUFFER_WRITER.lock().scrollback_page_up(),
+            Hotkey::ScrollbackPageDown => crate::FRAME_BUFFER_WRITER.lock().scrollback_page_down(),
+            Hotkey::SwitchTerminal(terminal) => crate::FRAME_BUFFER_WRITER.lock().switch_terminal(terminal),
+        }
+    }
+}
+
+/// What a scancode completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Key(DecodedKey),
+    Hotkey(Hotkey),
+}
+
+/// Feeds a scancode to the keyboard decoder. Returns the key or hotkey once a full key press has been
+/// decoded. Every press and release is also published as a KeyEvent.
+fn decode_scancode(scancode: u8) -> Option<Input> {
+    let mut keyboard = KEYBOARD.lock();
+    let key_event = match keyboard.add_byte(scancode) {
+        Ok(Some(key_event)) => key_event,
+        _ => return None,
+    };
+    let (code, state) = (key_event.code, key_event.state);
+    let key = keyboard.process_keyevent(key_event);
+    drop(keyboard);
+
+    let modifiers = {
+        let mut modifiers = MODIFIERS.lock();
+        modifiers.update(code, state);
+        *modifiers
+    };
+    let unicode = match key {
+        Some(DecodedKey::Unicode(c)) => Some(c),
+        _ => None,
+    };
+    event::publish(KeyEvent { code, state, modifiers, unicode });
+
+    match key? {
+        DecodedKey::RawKey(KeyCode::PageUp) if modifiers.shift => Some(Input::Hotkey(Hotkey::ScrollbackPageUp)),
+        DecodedKey::RawKey(KeyCode::PageDown) if modifiers.shift => Some(Input::Hotkey(Hotkey::ScrollbackPageDown)),
+        DecodedKey::RawKey(code) if modifiers.alt || modifiers.alt_gr => match terminal_of(code) {
+            Some(terminal) => Some(Input::Hotkey(Hotkey::SwitchTerminal(terminal))),
+            None => Some(Input::Key(DecodedKey::RawKey(code))),
+        },
+        key => Some(Input::Key(key)),
+    }
+}
+
+/// Runs a hotkey, or queues a key for the virtual terminal that is shown.
+fn handle_scancode(scancode: u8) {
+    match decode_scancode(scancode) {
+        Some(Input::Hotkey(hotkey)) => hotkey.run(),
+        Some(Input::Key(key)) => route_key(key),
+        None => {}
+    }
+}
+
+/// Decodes every scancode, so that the hotkeys work even when no task reads keys, e.g. to scroll
+/// back or reach the other terminals after the prompts are done. Spawned by main; KeyStream and KeyEventStream only get
+/// keys while it runs. Only one should run, as it reads from ScancodeStream.
+pub async fn keyboard_task() {
+    use futures_util::stream::StreamExt;
+    init_key_queues();
+    let mut scancodes = ScancodeStream::new();
+    while let Some(scancode) = scancodes.next().await {
+        handle_scancode(scancode);
+    }
+}
+
+/// Returns the virtual terminal that Alt plus the given key switches to.
+fn terminal_of(code: KeyCode) -> Option<usize> {
+    let terminal = match code {
+        KeyCode::F1 => 0,
+        KeyCode::F2 => 1,
+        KeyCode::F3 => 2,
+        KeyCode::F4 => 3,
+        KeyCode::F5 => 4,
+        KeyCode::F6 => 5,
+        _ => return None,
+    };
+    (terminal < TERMINAL_COUNT).then_some(terminal)
+}
+
+/// Stream of the keys typed while the given virtual terminal is shown, as routed by keyboard_task.
+/// Only one task should read the keys of a terminal at a time.
+pub struct KeyStream {
+    terminal: usize,
+}
+
+impl KeyStream {
+    pub fn new(terminal: usize) -> Self {
+        init_key_queues();
+        KeyStream {
+            terminal: terminal.min(TERMINAL_COUNT - 1),
+        }
+    }
+}
+
+fn init_key_queues() {
+    //shared by all streams, so only the first call allocates them
+    let _ = KEY_QUEUES.try_init_once(|| core::array::from_fn(|_| ArrayQueue::new(KEY_QUEUE_CAPACITY)));
+}
+
+/// Queues a key for the virtual terminal that is shown.
+fn route_key(key: DecodedKey) {
+    let terminal = crate::FRAME_BUFFER_WRITER.lock().active_terminal();
+    let queues = KEY_QUEUES.try_get().expect("key queues not initialized");
+    //like scancodes, a key is dropped if its terminal has too many waiting
+    if queues[terminal].push(key).is_ok() {
+        KEY_WAKERS[terminal].wake();
+    }
+}
+
+impl Stream for KeyStream {
+    type Item = DecodedKey;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<DecodedKey>> {
+        let queue = &KEY_QUEUES.try_get().expect("key queues not initialized")[self.terminal];
+        if let Some(key) = queue.pop() {
+            return Poll::Ready(Some(key));
+        }
+        KEY_WAKERS[self.terminal].register(cx.waker());
+        //check again, as another task might have routed a key before the waker was registered
+        match queue.pop() {
+            Some(key) => {
+                KEY_WAKERS[self.terminal].take();
+                Poll::Ready(Some(key))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn scrollback_hotkeys_need_no_reader() {
+        //scancode set 1, as keyboard_task would see it. No KeyStream exists in the tests
+        assert_eq!(decode_scancode(0x2A), None); //Shift down
+        assert_eq!(decode_scancode(0xE0), None);
+        assert_eq!(decode_scancode(0x49), Some(Input::Hotkey(Hotkey::ScrollbackPageUp)));
+        //release both, so that the next test starts with nothing held
+        for scancode in [0xE0, 0xC9, 0xAA] {
+            assert_eq!(decode_scancode(scancode), None);
+        }
+    }
+
+    #[test_case]
+    fn alt_f_keys_switch_terminals_without_a_reader() {
+        decode_scancode(0x38); //Alt down, which pc_keyboard returns as a raw key
+        assert_eq!(decode_scancode(0x3C), Some(Input::Hotkey(Hotkey::SwitchTerminal(1)))); //F2
+        for scancode in [0xBC, 0xB8] {
+            assert_eq!(decode_scancode(scancode), None);
+        }
+    }
+}
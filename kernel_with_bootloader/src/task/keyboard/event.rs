@@ -0,0 +1,180 @@
+//Key events for programs that need more than text: games (is a key held down?), editors and
+//shortcuts (modifiers, arrow and function keys). Every subscriber gets every event in its own queue,
+//whichever virtual terminal is shown.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+use pc_keyboard::{KeyCode, KeyState};
+use spin::Mutex;
+
+/// Maximum number of events waiting for each subscriber. Further events are dropped until it reads.
+const EVENT_QUEUE_CAPACITY: usize = 100;
+
+/// Which modifier keys are held down, and which locks are on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    /// Either shift key, see shift_left and shift_right.
+    pub shift: bool,
+    /// Either control key, see ctrl_left and ctrl_right.
+    pub ctrl: bool,
+    pub shift_left: bool,
+    pub shift_right: bool,
+    pub ctrl_left: bool,
+    pub ctrl_right: bool,
+    pub alt: bool,
+    pub alt_gr: bool, //the right alt key
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+impl Modifiers {
+    /// Nothing held, num lock on like pc_keyboard assumes.
+    pub const fn new() -> Self {
+        Modifiers {
+            shift: false,
+            ctrl: false,
+            shift_left: false,
+            shift_right: false,
+            ctrl_left: false,
+            ctrl_right: false,
+            alt: false,
+            alt_gr: false,
+            caps_lock: false,
+            num_lock: true,
+        }
+    }
+
+    /// Takes a key press or release into account.
+    pub(super) fn update(&mut self, code: KeyCode, state: KeyState) {
+        let down = state == KeyState::Down;
+        match code {
+            KeyCode::ShiftLeft => self.shift_left = down,
+            KeyCode::ShiftRight => self.shift_right = down,
+            KeyCode::ControlLeft => self.ctrl_left = down,
+            KeyCode::ControlRight => self.ctrl_right = down,
+            KeyCode::AltLeft => self.alt = down,
+            KeyCode::AltRight => self.alt_gr = down,
+            KeyCode::CapsLock if down => self.caps_lock = !self.caps_lock,
+            KeyCode::NumpadLock if down => self.num_lock = !self.num_lock,
+            _ => {}
+        }
+        //releasing one of two held shift keys must not release shift
+        self.shift = self.shift_left || self.shift_right;
+        self.ctrl = self.ctrl_left || self.ctrl_right;
+    }
+}
+
+/// A key being pressed or released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// The modifiers after this event, e.g. shift is set for the press of the shift key.
+    pub modifiers: Modifiers,
+    /// The char the key gives with the layout and modifiers, for presses only. Ctrl+letter gives
+    /// U+0001..U+001A, see set_handle_control.
+    pub unicode: Option<char>,
+}
+
+impl KeyEvent {
+    pub fn is_press(&self) -> bool {
+        self.state == KeyState::Down
+    }
+}
+
+struct Subscriber {
+    events: ArrayQueue<KeyEvent>,
+    waker: AtomicWaker,
+}
+
+//Weak, so that a dropped KeyEventStream unsubscribes
+static SUBSCRIBERS: Mutex<Vec<Weak<Subscriber>>> = Mutex::new(Vec::new());
+
+/// Hands an event to every subscriber.
+pub(super) fn publish(event: KeyEvent) {
+    SUBSCRIBERS.lock().retain(|subscriber| match subscriber.upgrade() {
+        Some(subscriber) => {
+            if subscriber.events.push(event).is_ok() {
+                subscriber.waker.wake();
+            }
+            true
+        }
+        None => false, //unsubscribed
+    });
+}
+
+/// Stream of all key events from the moment it was created, see subscribe.
+/// The events are published by super::keyboard_task.
+pub struct KeyEventStream {
+    subscriber: Arc<Subscriber>,
+}
+
+/// Starts receiving key events. Must only be called once the heap is initialized.
+pub fn subscribe() -> KeyEventStream {
+    super::init_key_queues();
+    let subscriber = Arc::new(Subscriber {
+        events: ArrayQueue::new(EVENT_QUEUE_CAPACITY),
+        waker: AtomicWaker::new(),
+    });
+    SUBSCRIBERS.lock().push(Arc::downgrade(&subscriber));
+    KeyEventStream { subscriber }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<KeyEvent>> {
+        let subscriber = &self.subscriber;
+        if let Some(event) = subscriber.events.pop() {
+            return Poll::Ready(Some(event));
+        }
+        subscriber.waker.register(cx.waker());
+        //check again, as another task might have published before the waker was registered
+        match subscriber.events.pop() {
+            Some(event) => {
+                subscriber.waker.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn modifiers_follow_presses_and_releases() {
+        let mut modifiers = Modifiers::new();
+        modifiers.update(KeyCode::ShiftLeft, KeyState::Down);
+        modifiers.update(KeyCode::CapsLock, KeyState::Down);
+        modifiers.update(KeyCode::CapsLock, KeyState::Up);
+        assert!(modifiers.shift && modifiers.caps_lock);
+        modifiers.update(KeyCode::ShiftLeft, KeyState::Up);
+        modifiers.update(KeyCode::CapsLock, KeyState::Down);
+        assert_eq!(modifiers, Modifiers::new());
+    }
+
+    #[test_case]
+    fn both_shift_keys_must_be_released() {
+        let mut modifiers = Modifiers::new();
+        modifiers.update(KeyCode::ShiftLeft, KeyState::Down);
+        modifiers.update(KeyCode::ShiftRight, KeyState::Down);
+        modifiers.update(KeyCode::ShiftLeft, KeyState::Up);
+        assert!(modifiers.shift && modifiers.shift_right && !modifiers.shift_left);
+        modifiers.update(KeyCode::ControlRight, KeyState::Down);
+        modifiers.update(KeyCode::ShiftRight, KeyState::Up);
+        assert!(!modifiers.shift && modifiers.ctrl);
+        modifiers.update(KeyCode::ControlRight, KeyState::Up);
+        assert_eq!(modifiers, Modifiers::new());
+    }
+}
@@ -0,0 +1,226 @@
+//Runtime choice of the keyboard layout and scancode set. pc_keyboard picks both with type parameters
+//of Keyboard, so we keep one Keyboard per step: one that only turns scancodes into key events (the
+//scancode set) and one that only turns key events into keys (the layout, which also tracks the modifiers).
+//Ref: https://wiki.osdev.org/PS/2_Keyboard#Scan_Code_Sets
+
+use pc_keyboard::{layouts, DecodedKey, Error, HandleControl, KeyEvent, Keyboard, ScancodeSet1, ScancodeSet2};
+
+/// The keyboard layouts of pc_keyboard, see set_layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    Azerty,
+    Dvorak,
+    Jis109,
+    De105,
+}
+
+impl Layout {
+    const NAMES: [(&'static str, Layout); 6] = [
+        ("us104", Layout::Us104),
+        ("uk105", Layout::Uk105),
+        ("azerty", Layout::Azerty),
+        ("dvorak", Layout::Dvorak),
+        ("jis109", Layout::Jis109),
+        ("de105", Layout::De105),
+    ];
+
+    /// Parses a name like "de105" or "Azerty", case insensitive. A const fn, so that boot_layout
+    /// can check KERNEL_KEYBOARD_LAYOUT when building.
+    pub const fn from_name(name: &str) -> Option<Layout> {
+        let mut i = 0;
+        while i < Self::NAMES.len() {
+            let (layout_name, layout) = Self::NAMES[i];
+            if eq_ignore_ascii_case(layout_name.as_bytes(), name.as_bytes()) {
+                return Some(layout);
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+//str::eq_ignore_ascii_case is not const
+const fn eq_ignore_ascii_case(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i].to_ascii_lowercase() != b[i].to_ascii_lowercase() {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// The scancode sets of pc_keyboard. PS/2 keyboards send set 2 after a reset, which the PS/2 controller
+/// translates to set 1 while its translation is on, which is what the BIOS and QEMU leave us with.
+/// keyboard::set_scancode_set turns the translation on or off to match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+//only the scancode set step of these is used
+enum ScancodeDecoder {
+    Set1(Keyboard<layouts::Us104Key, ScancodeSet1>),
+    Set2(Keyboard<layouts::Us104Key, ScancodeSet2>),
+}
+
+//only the layout step of these is used
+enum LayoutDecoder {
+    Us104(Keyboard<layouts::Us104Key, ScancodeSet1>),
+    Uk105(Keyboard<layouts::Uk105Key, ScancodeSet1>),
+    Azerty(Keyboard<layouts::Azerty, ScancodeSet1>),
+    Dvorak(Keyboard<layouts::Dvorak104Key, ScancodeSet1>),
+    Jis109(Keyboard<layouts::Jis109Key, ScancodeSet1>),
+    De105(Keyboard<layouts::De105Key, ScancodeSet1>),
+}
+
+/// A pc_keyboard Keyboard whose layout and scancode set can be changed.
+pub struct Decoder {
+    layout: Layout,
+    scancode_set: ScancodeSet,
+    handle_control: HandleControl,
+    scancodes: ScancodeDecoder,
+    keys: LayoutDecoder,
+}
+
+impl Decoder {
+    pub fn new(layout: Layout, scancode_set: ScancodeSet, handle_control: HandleControl) -> Self {
+        Decoder {
+            layout,
+            scancode_set,
+            handle_control,
+            scancodes: ScancodeDecoder::new(scancode_set),
+            keys: LayoutDecoder::new(layout, handle_control),
+        }
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Forgets the modifiers being held.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        self.keys = LayoutDecoder::new(layout, self.handle_control);
+    }
+
+    pub fn scancode_set(&self) -> ScancodeSet {
+        self.scancode_set
+    }
+
+    /// Forgets a key event that is half decoded.
+    pub fn set_scancode_set(&mut self, scancode_set: ScancodeSet) {
+        self.scancode_set = scancode_set;
+        self.scancodes = ScancodeDecoder::new(scancode_set);
+    }
+
+    /// MapLettersToUnicode turns Ctrl+A..Ctrl+Z into U+0001..U+001A, e.g. Ctrl+C into U+0003.
+    /// With Ignore they give the letters.
+    pub fn set_handle_control(&mut self, handle_control: HandleControl) {
+        self.handle_control = handle_control;
+        self.keys.set_ctrl_handling(handle_control);
+    }
+
+    /// Feeds a byte from the keyboard. Returns a key event once a scancode is complete.
+    pub fn add_byte(&mut self, byte: u8) -> Result<Option<KeyEvent>, Error> {
+        match &mut self.scancodes {
+            ScancodeDecoder::Set1(keyboard) => keyboard.add_byte(byte),
+            ScancodeDecoder::Set2(keyboard) => keyboard.add_byte(byte),
+        }
+    }
+
+    /// Applies the layout and the modifiers to a key event. None for e.g. shift or a key release.
+    pub fn process_keyevent(&mut self, event: KeyEvent) -> Option<DecodedKey> {
+        match &mut self.keys {
+            LayoutDecoder::Us104(keyboard) => keyboard.process_keyevent(event),
+            LayoutDecoder::Uk105(keyboard) => keyboard.process_keyevent(event),
+            LayoutDecoder::Azerty(keyboard) => keyboard.process_keyevent(event),
+            LayoutDecoder::Dvorak(keyboard) => keyboard.process_keyevent(event),
+            LayoutDecoder::Jis109(keyboard) => keyboard.process_keyevent(event),
+            LayoutDecoder::De105(keyboard) => keyboard.process_keyevent(event),
+        }
+    }
+}
+
+impl ScancodeDecoder {
+    fn new(scancode_set: ScancodeSet) -> Self {
+        //the layout does not matter here
+        match scancode_set {
+            ScancodeSet::Set1 => ScancodeDecoder::Set1(Keyboard::new(layouts::Us104Key, ScancodeSet1, HandleControl::Ignore)),
+            ScancodeSet::Set2 => ScancodeDecoder::Set2(Keyboard::new(layouts::Us104Key, ScancodeSet2, HandleControl::Ignore)),
+        }
+    }
+}
+
+impl LayoutDecoder {
+    fn new(layout: Layout, handle_control: HandleControl) -> Self {
+        //the scancode set does not matter here
+        match layout {
+            Layout::Us104 => LayoutDecoder::Us104(Keyboard::new(layouts::Us104Key, ScancodeSet1, handle_control)),
+            Layout::Uk105 => LayoutDecoder::Uk105(Keyboard::new(layouts::Uk105Key, ScancodeSet1, handle_control)),
+            Layout::Azerty => LayoutDecoder::Azerty(Keyboard::new(layouts::Azerty, ScancodeSet1, handle_control)),
+            Layout::Dvorak => LayoutDecoder::Dvorak(Keyboard::new(layouts::Dvorak104Key, ScancodeSet1, handle_control)),
+            Layout::Jis109 => LayoutDecoder::Jis109(Keyboard::new(layouts::Jis109Key, ScancodeSet1, handle_control)),
+            Layout::De105 => LayoutDecoder::De105(Keyboard::new(layouts::De105Key, ScancodeSet1, handle_control)),
+        }
+    }
+
+    fn set_ctrl_handling(&mut self, handle_control: HandleControl) {
+        match self {
+            LayoutDecoder::Us104(keyboard) => keyboard.set_ctrl_handling(handle_control),
+            LayoutDecoder::Uk105(keyboard) => keyboard.set_ctrl_handling(handle_control),
+            LayoutDecoder::Azerty(keyboard) => keyboard.set_ctrl_handling(handle_control),
+            LayoutDecoder::Dvorak(keyboard) => keyboard.set_ctrl_handling(handle_control),
+            LayoutDecoder::Jis109(keyboard) => keyboard.set_ctrl_handling(handle_control),
+            LayoutDecoder::De105(keyboard) => keyboard.set_ctrl_handling(handle_control),
+        }
+    }
+}
+
+//evaluated when building, so that a typo in the variables fails the build instead of being ignored
+const BOOT_LAYOUT: Layout = match option_env!("KERNEL_KEYBOARD_LAYOUT") {
+    None => Layout::Us104,
+    Some(name) => match Layout::from_name(name) {
+        Some(layout) => layout,
+        None => panic!("KERNEL_KEYBOARD_LAYOUT must be one of us104, uk105, azerty, dvorak, jis109, de105"),
+    },
+};
+
+const BOOT_SCANCODE_SET: ScancodeSet = match option_env!("KERNEL_SCANCODE_SET") {
+    None => ScancodeSet::Set1,
+    Some(set) => match set.as_bytes() {
+        b"1" => ScancodeSet::Set1,
+        b"2" => ScancodeSet::Set2,
+        _ => panic!("KERNEL_SCANCODE_SET must be 1 or 2"),
+    },
+};
+
+/// The layout at boot: KERNEL_KEYBOARD_LAYOUT when the kernel was built, e.g.
+/// `KERNEL_KEYBOARD_LAYOUT=de105 cargo run`, else Us104. An unknown name fails the build.
+pub fn boot_layout() -> Layout {
+    BOOT_LAYOUT
+}
+
+/// The scancode set at boot: KERNEL_SCANCODE_SET (1 or 2) when the kernel was built, else set 1.
+pub fn boot_scancode_set() -> ScancodeSet {
+    BOOT_SCANCODE_SET
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn layout_names_are_case_insensitive() {
+        assert_eq!(Layout::from_name("de105"), Some(Layout::De105));
+        assert_eq!(Layout::from_name("AZERTY"), Some(Layout::Azerty));
+        assert_eq!(Layout::from_name("colemak"), None);
+    }
+}
@@ -0,0 +1,262 @@
+//PS/2 mouse, the auxiliary device of the 8042 controller, on IRQ12.
+//Like the keyboard, the interrupt handler only pushes the raw bytes into a lock-free queue.
+//MouseStream puts them together into packets of 3 bytes, or 4 if the mouse has a wheel,
+//moves the pointer on the screen (see writer::pointer) and returns a MouseEvent for each packet.
+//Ref: https://wiki.osdev.org/PS/2_Mouse and https://wiki.osdev.org/%228042%22_PS/2_Controller
+
+use conquer_once::spin::OnceCell;
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+use crossbeam_queue::ArrayQueue;
+use futures_util::{stream::Stream, task::AtomicWaker};
+use spin::Mutex;
+
+use crate::ps2;
+
+/// Maximum number of bytes kept while no task is reading them, i.e. 25 to 33 packets. Further bytes are dropped.
+const BYTE_QUEUE_CAPACITY: usize = 100;
+
+static BYTE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+/// 3 for a standard mouse, 4 once init has turned on the wheel.
+static PACKET_SIZE: AtomicUsize = AtomicUsize::new(3);
+
+//mouse commands, each answered with ACK
+const SET_DEFAULTS: u8 = 0xF6;
+const SET_SAMPLE_RATE: u8 = 0xF3;
+const GET_DEVICE_ID: u8 = 0xF2;
+const ENABLE_REPORTING: u8 = 0xF4;
+const ACK: u8 = 0xFA;
+
+/// IntelliMouse: setting these sample rates in a row turns on the wheel, after which the mouse reports ID 3.
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+const WHEEL_MOUSE_ID: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    /// The controller or the mouse did not answer.
+    Timeout,
+    /// The mouse answered a command with this instead of ACK.
+    NoAck(u8),
+}
+
+impl From<ps2::Timeout> for MouseError {
+    fn from(_: ps2::Timeout) -> Self {
+        MouseError::Timeout
+    }
+}
+
+/// Sends a command or its argument to the mouse and waits for the ACK.
+fn send_to_mouse(byte: u8) -> Result<(), MouseError> {
+    ps2::write_command(ps2::WRITE_TO_AUX)?;
+    ps2::write_data(byte)?;
+    match ps2::read_aux_data()? {
+        ACK => Ok(()),
+        other => Err(MouseError::NoAck(other)),
+    }
+}
+
+/// Enables the mouse port of the 8042 with its interrupt, turns on the wheel if there is one and
+/// starts the stream of packets. Called by interrupts::init before interrupts are enabled, as the answers
+/// come through the same data port that the interrupt handlers read.
+///
+/// The keyboard port is disabled meanwhile, see ps2::without_keyboard.
+pub(crate) fn init() -> Result<(), MouseError> {
+    ps2::without_keyboard(init_mouse)
+}
+
+fn init_mouse() -> Result<(), MouseError> {
+    ps2::write_command(ps2::ENABLE_AUX)?;
+    ps2::update_config(|config| (config | ps2::CONFIG_AUX_INTERRUPT) & !ps2::CONFIG_AUX_CLOCK_DISABLED)?;
+
+    send_to_mouse(SET_DEFAULTS)?;
+    for rate in WHEEL_SEQUENCE {
+        send_to_mouse(SET_SAMPLE_RATE)?;
+        send_to_mouse(rate)?;
+    }
+    send_to_mouse(GET_DEVICE_ID)?;
+    let packet_size = if ps2::read_aux_data()? == WHEEL_MOUSE_ID { 4 } else { 3 };
+    PACKET_SIZE.store(packet_size, Ordering::Relaxed);
+
+    send_to_mouse(ENABLE_REPORTING)
+}
+
+/// Called by the mouse interrupt handler.
+///
+/// Must not block or allocate.
+pub(crate) fn add_byte(byte: u8) {
+    if let Ok(queue) = BYTE_QUEUE.try_get() {
+        if queue.push(byte).is_ok() {
+            WAKER.wake();
+        }
+    }
+    //queue not yet initialized means nobody is listening, so the byte is simply dropped
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// One packet from the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement in pixels, right and down are positive as on the screen.
+    pub dx: i16,
+    pub dy: i16,
+    /// Wheel clicks, down (towards the user) is positive. Always 0 without a wheel.
+    pub wheel: i8,
+    /// The buttons held down.
+    pub buttons: MouseButtons,
+    /// Where the pointer is after this movement.
+    pub x: usize,
+    pub y: usize,
+}
+
+//first byte of a packet
+const LEFT_BUTTON: u8 = 1 << 0;
+const RIGHT_BUTTON: u8 = 1 << 1;
+const MIDDLE_BUTTON: u8 = 1 << 2;
+const ALWAYS_SET: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+/// Turns a 3 or 4 byte packet into an event, without the pointer position.
+fn decode_packet(packet: &[u8]) -> MouseEvent {
+    let flags = packet[0];
+    //9 bit two's complement movement: the sign bit is in the first byte
+    let movement = |byte: u8, sign: u8, overflow: u8| match flags & overflow {
+        0 if flags & sign != 0 => byte as i16 - 0x100,
+        0 => byte as i16,
+        _ => 0, //moved too fast to count, better to not move at all than to jump
+    };
+    MouseEvent {
+        dx: movement(packet[1], X_SIGN, X_OVERFLOW),
+        dy: -movement(packet[2], Y_SIGN, Y_OVERFLOW), //the mouse counts up as positive
+        wheel: packet.get(3).map_or(0, |&wheel| wheel as i8),
+        buttons: MouseButtons {
+            left: flags & LEFT_BUTTON != 0,
+            right: flags & RIGHT_BUTTON != 0,
+            middle: flags & MIDDLE_BUTTON != 0,
+        },
+        x: 0,
+        y: 0,
+    }
+}
+
+/// Collects bytes until a packet is complete.
+struct PacketAssembler {
+    bytes: [u8; 4],
+    len: usize,
+}
+
+impl PacketAssembler {
+    const fn new() -> Self {
+        PacketAssembler { bytes: [0; 4], len: 0 }
+    }
+
+    /// Returns the event once the last byte of a packet has been added.
+    fn add(&mut self, byte: u8, packet_size: usize) -> Option<MouseEvent> {
+        //bit 3 is set in every first byte. If not, a byte got lost, so skip until the next packet starts
+        if self.len == 0 && byte & ALWAYS_SET == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < packet_size {
+            return None;
+        }
+        self.len = 0;
+        Some(decode_packet(&self.bytes[..packet_size]))
+    }
+}
+
+//survives across streams, as a packet may be half read when one is dropped
+static ASSEMBLER: Mutex<PacketAssembler> = Mutex::new(PacketAssembler::new());
+
+/// Stream of mouse events. Reading it is what moves the pointer, so keep one task reading.
+/// Only one task should read from it at a time, as only the last registered waker is woken.
+pub struct MouseStream {
+    _private: (),
+}
+
+impl MouseStream {
+    pub fn new() -> Self {
+        //the queue is shared by all streams, so only the first call allocates it
+        let _ = BYTE_QUEUE.try_init_once(|| ArrayQueue::new(BYTE_QUEUE_CAPACITY));
+        MouseStream { _private: () }
+    }
+
+    /// Assembles the queued bytes until a packet is complete, then moves the pointer.
+    fn next_event(queue: &ArrayQueue<u8>) -> Option<MouseEvent> {
+        let packet_size = PACKET_SIZE.load(Ordering::Relaxed);
+        let mut assembler = ASSEMBLER.lock();
+        while let Some(byte) = queue.pop() {
+            if let Some(mut event) = assembler.add(byte, packet_size) {
+                (event.x, event.y) = crate::FRAME_BUFFER_WRITER
+                    .lock()
+                    .move_pointer(event.dx as isize, event.dy as isize);
+                return Some(event);
+            }
+        }
+        None
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<MouseEvent>> {
+        let queue = BYTE_QUEUE.try_get().expect("mouse byte queue not initialized");
+
+        if let Some(event) = Self::next_event(queue) {
+            return Poll::Ready(Some(event));
+        }
+
+        WAKER.register(cx.waker());
+        //check again, as the interrupt handler might have pushed before the waker was registered
+        match Self::next_event(queue) {
+            Some(event) => {
+                WAKER.take();
+                Poll::Ready(Some(event))
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Shows the pointer where the mouse moves it, without doing anything with the clicks.
+/// Spawn it when nothing else reads a MouseStream.
+pub async fn pointer_task() {
+    use futures_util::stream::StreamExt;
+    crate::FRAME_BUFFER_WRITER.lock().set_pointer_visible(true);
+    let mut events = MouseStream::new();
+    while events.next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn packets_are_synced_and_decoded() {
+        let mut assembler = PacketAssembler::new();
+        //a stray byte without bit 3 is skipped
+        assert_eq!(assembler.add(0x00, 4), None);
+        //left button, moved left by 2 and up by 3, wheel 1 down
+        assert_eq!(assembler.add(ALWAYS_SET | LEFT_BUTTON | X_SIGN, 4), None);
+        assert_eq!(assembler.add(0xFE, 4), None);
+        assert_eq!(assembler.add(0x03, 4), None);
+        let event = assembler.add(0x01, 4).unwrap();
+        assert_eq!((event.dx, event.dy, event.wheel), (-2, -3, 1));
+        assert_eq!(event.buttons, MouseButtons { left: true, right: false, middle: false });
+    }
+}
@@ -0,0 +1,93 @@
+//In-kernel test framework based on custom_test_frameworks.
+//Functions marked #[test_case] are collected by `cargo test` and run by test_runner below.
+//Results go to the serial port and QEMU is shut down through the isa-debug-exit device,
+//so the host can turn the QEMU exit code into pass or fail (see tests/kernel_tests.rs of the host crate).
+//Ref: https://os.phil-opp.com/testing/
+
+use crate::{serial_print, serial_println};
+use x86_64::instructions::port::Port;
+
+/// I/O port of the isa-debug-exit device. Must match the iobase passed to QEMU.
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Value written to the isa-debug-exit device. QEMU then exits with status (value << 1) | 1,
+/// i.e. 33 for Success and 35 for Failed. 0 and 1 are avoided as QEMU uses them itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    unsafe {
+        let mut port = Port::new(ISA_DEBUG_EXIT_PORT);
+        port.write(exit_code as u32);
+    }
+    //only reached if QEMU was started without the isa-debug-exit device
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Implemented for every test function so that the runner can print its name.
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T> Testable for T
+where
+    T: Fn(),
+{
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    #[cfg(feature = "stack_overflow_test")]
+    stack_overflow_test(); //does not return, so it runs alone
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// The panic of interrupts::double_fault_handler.
+#[cfg(feature = "stack_overflow_test")]
+const DOUBLE_FAULT_PANIC: &str = "EXCEPTION: DOUBLE FAULT";
+
+/// Overflows the kernel stack into the guard page below it. The page fault cannot push its stack frame
+/// there, so the CPU raises a double fault, whose handler runs on its own stack (see gdt.rs) and panics.
+/// Without that stack the CPU would triple fault, which with -no-reboot ends QEMU with exit code 0.
+#[cfg(feature = "stack_overflow_test")]
+fn stack_overflow_test() -> ! {
+    #[allow(unconditional_recursion)]
+    fn recurse(depth: u64) -> u64 {
+        //using the result after the call keeps it from being turned into a loop
+        core::hint::black_box(recurse(depth + 1)) + depth
+    }
+
+    serial_print!("stack_overflow_ends_in_a_double_fault...\t");
+    crate::interrupts::init_idt();
+    recurse(0);
+    serial_println!("[failed]\n");
+    serial_println!("Error: the stack did not overflow\n");
+    exit_qemu(QemuExitCode::Failed);
+}
+
+/// Called by the panic handler in test mode. A panic means the running test failed, except for the
+/// double fault that stack_overflow_test expects.
+pub fn test_panic_handler(info: &core::panic::PanicInfo) -> ! {
+    #[cfg(feature = "stack_overflow_test")]
+    if info.message().as_str() == Some(DOUBLE_FAULT_PANIC) {
+        serial_println!("[ok]");
+        exit_qemu(QemuExitCode::Success);
+    }
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+}
@@ -1,11 +1,34 @@
+pub(crate) mod ansi;
+mod back_buffer;
+mod color;
 mod constants;
+mod font;
+mod pointer;
+mod psf;
+mod scrollback;
+mod terminal;
 
 use core::{fmt, ptr};
 
+use ansi::{Action, CsiSequence, Parser};
+use back_buffer::BackBuffer;
+use pointer::Pointer;
+use scrollback::{Cell, Scrollback};
+use terminal::TerminalState;
+use color::{DEFAULT_BACKGROUND, DEFAULT_FOREGROUND};
+pub use color::Color;
+
+use crate::graphics::{Canvas, Rect};
 use bootloader_api::info::{FrameBufferInfo, PixelFormat};
 use constants::font_constants;
-use constants::font_constants::{BACKSPACE, BACKUP_CHAR, CHAR_RASTER_HEIGHT, FONT_WEIGHT};
-use noto_sans_mono_bitmap::{get_raster, RasterizedChar};
+use constants::font_constants::{BACKSPACE, BACKUP_CHAR, DEFAULT_FONT_WEIGHT, RASTER_HEIGHTS};
+use alloc::{boxed::Box, vec::Vec};
+use font::NotoFont;
+use noto_sans_mono_bitmap::get_raster_width;
+pub use font::{Font, Glyph};
+pub use psf::PsfFont;
+pub use terminal::{TerminalWriter, TERMINAL_COUNT};
+pub use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
 
 /// Additional vertical space between lines
 const LINE_SPACING: usize = 2;
@@ -16,20 +39,105 @@ const LETTER_SPACING: usize = 0;
 /// Padding from the border. Prevent that font is too close to border.
 const BORDER_PADDING: usize = 2;
 
-/// Returns the raster of the given char or the raster of [font_constants::BACKUP_CHAR].
-fn get_char_raster(c: char) -> RasterizedChar {
-    fn get(c: char) -> Option<RasterizedChar> {
-        get_raster(c, FONT_WEIGHT, CHAR_RASTER_HEIGHT)
+/// Returns the biggest raster height that fits font_constants::MIN_COLUMNS x MIN_LINES chars
+/// on the screen, so that text is readable on high resolutions too. The smallest one if none fits.
+fn auto_raster_height(info: &FrameBufferInfo) -> RasterHeight {
+    let fits = |height: RasterHeight| {
+        let char_width = get_raster_width(DEFAULT_FONT_WEIGHT, height) + LETTER_SPACING;
+        let line_height = height.val() + LINE_SPACING;
+        let columns = info.width.saturating_sub(2 * BORDER_PADDING) / char_width;
+        let lines = info.height.saturating_sub(2 * BORDER_PADDING) / line_height;
+        columns >= font_constants::MIN_COLUMNS && lines >= font_constants::MIN_LINES
+    };
+    RASTER_HEIGHTS
+        .into_iter()
+        .rev()
+        .find(|&height| fits(height))
+        .unwrap_or(RASTER_HEIGHTS[0])
+}
+
+/// Moves the pixels of region up by the given number of rows. The rows at the bottom keep their old content.
+fn scroll_pixels(pixels: &mut [u8], info: &FrameBufferInfo, region: Rect, rows: usize) {
+    //a row of pixels takes stride pixels in the buffer, which can be more than width
+    let row_bytes = info.stride * info.bytes_per_pixel;
+    let scrolled_bytes = rows * row_bytes;
+    if region.x == 0 && region.width == info.width {
+        //one memmove of whole rows, independent of the pixel format
+        let start = region.y * row_bytes;
+        let end = region.bottom() * row_bytes;
+        pixels.copy_within(start + scrolled_bytes..end, start);
+    } else {
+        //only the part of each row inside the region may move
+        for row in region.y..region.bottom() - rows {
+            let start = row * row_bytes + region.x * info.bytes_per_pixel;
+            let end = start + region.width * info.bytes_per_pixel;
+            pixels.copy_within(start + scrolled_bytes..end + scrolled_bytes, start);
+        }
+    }
+}
+
+/// Number of lines kept after they scrolled off the screen, see init_scrollback.
+pub const SCROLLBACK_LINES: usize = 1000;
+
+/// Height of the bar drawn under the current position when the cursor is visible.
+const CURSOR_HEIGHT: usize = 2;
+
+/// Text attributes, set with SGR escape sequences (ESC [ ... m).
+#[derive(Debug, Clone, Copy)]
+struct Attributes {
+    foreground: Color,
+    background: Color,
+    bold: bool,
+}
+
+impl Attributes {
+    const fn new() -> Self {
+        Attributes {
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            bold: false,
+        }
     }
-    get(c).unwrap_or_else(|| get(BACKUP_CHAR).expect("Should get raster of backup char."))
 }
 
 /// Allows logging text to a pixel-based framebuffer.
+///
+/// The text is kept as a grid of cells (char plus attributes, see scrollback) once init_scrollback
+/// has been called, and drawn into a back buffer once init_back_buffer has been called. Output is
+/// then copied to the framebuffer by flush, which write_fmt (i.e. every print!) calls when done.
+///
+/// Understands the common VT100/ANSI escape sequences: SGR colors (16, 256 and 24 bit) and bold,
+/// cursor movement and positioning (CUU, CUD, CUF, CUB, CUP), erase in line/display (EL, ED),
+/// save/restore cursor (ESC 7, ESC 8, CSI s, CSI u) and cursor visibility (CSI ? 25 h/l).
+///
+/// Text only goes into the text region, the whole screen by default, so that the rest can be used
+/// for graphics (see draw and the graphics module).
+///
+/// There are TERMINAL_COUNT virtual terminals once init_terminals has been called. The fields from
+/// x_pos to scrollback belong to the one that is shown, see the terminal module.
+///
+/// A mouse pointer can be shown over everything, see set_pointer_visible.
 pub struct FrameBufferWriter {
     framebuffer: Option<&'static mut [u8]>,
     info: FrameBufferInfo,
+    text_region: Rect,
+    raster_height: RasterHeight,
+    font_weight: FontWeight,
+    fallback_fonts: Vec<Box<dyn Font>>, //asked in order for chars the built-in font does not have
     x_pos: usize,
     y_pos: usize,
+    attributes: Attributes,
+    ansi_parser: Parser,
+    saved_cursor: (usize, usize, Attributes),
+    cursor_visible: bool,
+    cursor_drawn: bool, //whether the cursor bar is currently inverted on screen
+    scrollback: Option<Scrollback>, //None until init_scrollback, as it lives on the heap
+    back_buffer: Option<BackBuffer>, //None until init_back_buffer. Without it we draw to the framebuffer directly
+    terminals: Vec<Option<TerminalState>>, //empty until init_terminals. None for the shown terminal
+    active_terminal: usize,
+    hidden: bool, //set while writing to a terminal that is not shown, so that nothing is drawn
+    flush_deferred: bool, //set while write_fmt runs, so that its write_str calls do not flush each piece
+    pointer: Pointer,
 }
 
 impl FrameBufferWriter {
@@ -49,8 +157,24 @@ impl FrameBufferWriter {
     pub fn empty() -> Self {
         let logger = Self {
             framebuffer: None,
+            text_region: Rect::new(0, 0, 0, 0),
+            raster_height: RASTER_HEIGHTS[0],
+            font_weight: DEFAULT_FONT_WEIGHT,
+            fallback_fonts: Vec::new(),
             x_pos: 0,
             y_pos: 0,
+            attributes: Attributes::new(),
+            ansi_parser: Parser::new(),
+            saved_cursor: (BORDER_PADDING, BORDER_PADDING, Attributes::new()),
+            cursor_visible: true,
+            cursor_drawn: false,
+            scrollback: None,
+            back_buffer: None,
+            terminals: Vec::new(),
+            active_terminal: 0,
+            hidden: false,
+            flush_deferred: false,
+            pointer: Pointer::new(),
             info: FrameBufferInfo {
                 // The total size in bytes.
                 byte_len: 0,
@@ -77,32 +201,358 @@ impl FrameBufferWriter {
     pub fn init(&mut self, framebuffer: &'static mut [u8], info: FrameBufferInfo) {
         self.framebuffer = Some(framebuffer);
         self.info = info;
+        self.text_region = Rect::new(0, 0, info.width, info.height);
+        self.raster_height = auto_raster_height(&info);
         self.x_pos = 0;
         self.y_pos = 0;
+        self.cursor_drawn = false;
+        self.back_buffer = None; //it would have the size of the old framebuffer
+        self.pointer = Pointer::new();
+
+        self.clear();
+        self.show_cursor();
+    }
+
+    /// Starts keeping the text written from now on, including max_history lines that scrolled off,
+    /// so that the history can be shown with scrollback_page_up/scrollback_page_down.
+    /// Must only be called once the heap is initialized.
+    pub fn init_scrollback(&mut self, max_history: usize) {
+        self.scrollback = Some(Scrollback::new(self.text_columns(), self.text_rows(), max_history));
+    }
+
+    /// Creates the other virtual terminals, blank, with as much history as the shown one. They are
+    /// repainted from their cells, so this does nothing before init_scrollback.
+    pub fn init_terminals(&mut self) {
+        let Some(max_history) = self.scrollback.as_ref().map(Scrollback::max_history) else {
+            return;
+        };
+        let (columns, rows) = (self.text_columns(), self.text_rows());
+        let (left, top) = (self.text_left(), self.text_top());
+        self.terminals = (0..TERMINAL_COUNT)
+            .map(|terminal| {
+                (terminal != self.active_terminal)
+                    .then(|| TerminalState::new(left, top, Scrollback::new(columns, rows, max_history)))
+            })
+            .collect();
+    }
+
+    /// Starts the cells over at the current text size, for set_font and set_text_region.
+    /// This wipes the history and the other virtual terminals, as their text would not fit anymore.
+    fn reset_cells(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_ref() {
+            let max_history = scrollback.max_history();
+            self.init_scrollback(max_history);
+        }
+        if !self.terminals.is_empty() {
+            self.init_terminals();
+        }
+    }
+
+    /// Returns a writer for the given virtual terminal, e.g. write!(writer.terminal(2), "hi").
+    /// What is written to a terminal that is not shown appears when switching to it.
+    /// Writes to the shown terminal if there are no virtual terminals yet or the number is too big.
+    pub fn terminal(&mut self, terminal: usize) -> TerminalWriter<'_> {
+        TerminalWriter { writer: self, terminal }
+    }
 
+    /// The number of the virtual terminal on the screen, 0 based.
+    pub fn active_terminal(&self) -> usize {
+        self.active_terminal
+    }
+
+    /// Shows the given virtual terminal, 0 based. Does nothing if there is no such terminal.
+    pub fn switch_terminal(&mut self, terminal: usize) {
+        let Some(mut state) = self.terminals.get_mut(terminal).and_then(Option::take) else {
+            return; //also the case for the shown one
+        };
+        self.hide_cursor();
+        state.swap(self);
+        self.terminals[self.active_terminal] = Some(state);
+        self.active_terminal = terminal;
+        self.repaint();
+        self.flush();
+    }
+
+    /// Runs f with the state of the given virtual terminal swapped in. Nothing is drawn if it is not shown.
+    fn with_terminal<R>(&mut self, terminal: usize, f: impl FnOnce(&mut Self) -> R) -> R {
+        let Some(mut state) = self.terminals.get_mut(terminal).and_then(Option::take) else {
+            return f(self); //the shown one, or no such terminal
+        };
+        self.hide_cursor(); //it is where the shown terminal's cursor is
+        state.swap(self);
+        self.hidden = true;
+        let result = f(self);
+        self.hidden = false;
+        state.swap(self);
+        self.terminals[terminal] = Some(state);
+        self.show_cursor();
+        result
+    }
+
+    /// Adds a font to the end of the fallback chain, e.g. a PsfFont with box-drawing or Cyrillic chars.
+    /// Its glyphs are drawn at the size of the built-in font: cut off on the right and bottom if bigger,
+    /// centered if smaller.
+    /// Must only be called once the heap is initialized.
+    pub fn add_fallback_font(&mut self, font: Box<dyn Font>) {
+        self.fallback_fonts.push(font);
+    }
+
+    /// Returns the glyph of the given char from the first font of the chain that has it,
+    /// else the glyph of [font_constants::BACKUP_CHAR].
+    fn get_glyph(&self, c: char) -> Glyph {
+        let noto = NotoFont {
+            raster_height: self.raster_height,
+            font_weight: self.font_weight,
+        };
+        noto.glyph(c)
+            .or_else(|| self.fallback_fonts.iter().find_map(|font| font.glyph(c)))
+            .unwrap_or_else(|| noto.glyph(BACKUP_CHAR).expect("Should get raster of backup char."))
+    }
+
+    /// The width of each char raster, as the font is mono space.
+    fn char_raster_width(&self) -> usize {
+        get_raster_width(self.font_weight, self.raster_height)
+    }
+
+    fn char_raster_height(&self) -> usize {
+        self.raster_height.val()
+    }
+
+    /// Restricts text output to the given area of the screen, e.g. to leave room for graphics.
+    /// Clears the area and moves the cursor to its top left corner. The scrollback is emptied and
+    /// all the other virtual terminals are wiped, as their lines would not fit anymore.
+    /// Text written to a region that is too small for one line is dropped.
+    pub fn set_text_region(&mut self, region: Rect) {
+        self.hide_cursor();
+        self.text_region = region.intersection(&Rect::new(0, 0, self.width(), self.height()));
+        self.reset_cells();
         self.clear();
+        self.show_cursor();
+        self.flush();
+    }
+
+    pub fn text_region(&self) -> Rect {
+        self.text_region
+    }
+
+    /// Runs draw with a Canvas on the screen, then shows what was drawn. Drawing over the text
+    /// region is possible, but the text there may be repainted over it.
+    pub fn draw<R>(&mut self, draw: impl FnOnce(&mut Canvas) -> R) -> R {
+        self.hide_cursor();
+        let info = self.info;
+        let mut canvas = Canvas::new(self.pixels(), info);
+        let result = draw(&mut canvas);
+        let damage = canvas.damage();
+        self.mark_dirty(damage.x, damage.y, damage.width, damage.height);
+        self.show_cursor();
+        self.flush();
+        result
+    }
+    /// Draws into a copy of the framebuffer in memory from now on, and only copies what changed
+    /// to the framebuffer. Makes printing a lot faster. Must only be called once the heap is initialized.
+    pub fn init_back_buffer(&mut self) {
+        let (char_width, line_height) = (self.char_width(), self.line_height());
+        let Some(framebuffer) = self.framebuffer.as_mut() else {
+            return;
+        };
+        self.pointer.erase(framebuffer, &self.info); //it must not end up in the copy
+        self.back_buffer = Some(BackBuffer::new(framebuffer, &self.info, char_width, line_height, BORDER_PADDING));
+        self.pointer.draw(framebuffer, &self.info);
+    }
+
+    /// Copies everything drawn since the last flush from the back buffer to the framebuffer.
+    pub fn flush(&mut self) {
+        if let (Some(back_buffer), Some(framebuffer)) = (self.back_buffer.as_mut(), self.framebuffer.as_mut()) {
+            //the pointer is not in the back buffer, so take it off the screen while copying over it
+            self.pointer.erase(framebuffer, &self.info);
+            back_buffer.flush(framebuffer);
+            self.pointer.draw(framebuffer, &self.info);
+            //make sure the writes to the framebuffer are not optimized away
+            let _ = unsafe { ptr::read_volatile(&framebuffer[0]) };
+        }
+    }
+
+    /// Shows or hides the mouse pointer. It needs the back buffer, so that nothing but the pointer
+    /// draws on the framebuffer directly; without one it stays hidden.
+    pub fn set_pointer_visible(&mut self, visible: bool) {
+        let Some(framebuffer) = self.framebuffer.as_mut() else {
+            return;
+        };
+        self.pointer.erase(framebuffer, &self.info);
+        self.pointer.set_visible(visible && self.back_buffer.is_some());
+        self.pointer.draw(framebuffer, &self.info);
+        let _ = unsafe { ptr::read_volatile(&framebuffer[0]) };
+    }
+
+    /// Moves the mouse pointer by dx, dy pixels, keeping its tip on the screen, and returns where it is now.
+    pub fn move_pointer(&mut self, dx: isize, dy: isize) -> (usize, usize) {
+        let (x, y) = self.pointer.position();
+        let x = (x as isize + dx).clamp(0, self.width().saturating_sub(1) as isize) as usize;
+        let y = (y as isize + dy).clamp(0, self.height().saturating_sub(1) as isize) as usize;
+        match self.framebuffer.as_mut() {
+            Some(framebuffer) => {
+                self.pointer.erase(framebuffer, &self.info);
+                self.pointer.set_position(x, y);
+                self.pointer.draw(framebuffer, &self.info);
+                let _ = unsafe { ptr::read_volatile(&framebuffer[0]) };
+            }
+            None => self.pointer.set_position(x, y),
+        }
+        (x, y)
+    }
+
+    /// Returns the pixels to draw into: the back buffer if there is one, else the framebuffer.
+    /// None at all when booted without a framebuffer, see the console module.
+    fn pixels(&mut self) -> &mut [u8] {
+        match self.back_buffer.as_mut() {
+            Some(back_buffer) => back_buffer.pixels_mut(),
+            None => self.framebuffer.as_deref_mut().unwrap_or(&mut []),
+        }
+    }
+
+    /// Marks the given rectangle as changed, so that the next flush copies it to the framebuffer.
+    fn mark_dirty(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        if let Some(back_buffer) = self.back_buffer.as_mut() {
+            back_buffer.mark_dirty(x, y, width, height);
+        }
+    }
+
+    fn mark_all_dirty(&mut self) {
+        if let Some(back_buffer) = self.back_buffer.as_mut() {
+            back_buffer.mark_all_dirty();
+        }
+    }
+
+    /// Shows the previous half screen of the history. Any output returns to the live screen.
+    pub fn scrollback_page_up(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            let lines = (scrollback.rows() / 2).max(1);
+            if scrollback.scroll_back(lines) {
+                self.repaint();
+                self.flush();
+            }
+        }
+    }
+
+    /// Shows the next half screen of the history, up to the live screen.
+    pub fn scrollback_page_down(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            let lines = (scrollback.rows() / 2).max(1);
+            if scrollback.scroll_forward(lines) {
+                self.repaint();
+                self.flush();
+            }
+        }
+    }
+
+    /// Returns to the live screen if the history is being viewed.
+    fn snap_back(&mut self) {
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            let offset = scrollback.view_offset();
+            if scrollback.scroll_forward(offset) {
+                self.repaint();
+            }
+        }
+    }
+
+    /// Redraws the whole screen from the scrollback, at the current view.
+    fn repaint(&mut self) {
+        if self.hidden {
+            return;
+        }
+        let Some(scrollback) = self.scrollback.take() else {
+            return;
+        };
+        self.hide_cursor();
+        let (x_pos, y_pos, attributes) = (self.x_pos, self.y_pos, self.attributes);
+        let region = self.text_region;
+        self.fill_rect(region.x, region.y, region.width, region.height, DEFAULT_BACKGROUND);
+        for row in 0..scrollback.rows() {
+            for (column, cell) in scrollback.visible_line(row).iter().enumerate() {
+                if cell.c == ' ' && cell.attributes.background == DEFAULT_BACKGROUND {
+                    continue; //already cleared above
+                }
+                self.attributes = cell.attributes;
+                self.x_pos = self.text_left() + column * self.char_width();
+                self.y_pos = self.text_top() + row * self.line_height();
+                self.write_glyph(self.get_glyph(cell.c));
+            }
+        }
+        (self.x_pos, self.y_pos, self.attributes) = (x_pos, y_pos, attributes);
+        let live = scrollback.view_offset() == 0;
+        self.scrollback = Some(scrollback);
+        if live {
+            self.show_cursor(); //no cursor while looking at the history
+        }
     }
 
     //Make it possible to set x, y positions with option to provide both or just one of them
     pub fn set_x_y_pos(&mut self, x_pos: Option<usize>, y_pos: Option<usize>){
+        self.hide_cursor();
         self.x_pos = x_pos.unwrap_or(self.x_pos);
         self.y_pos = y_pos.unwrap_or(self.y_pos);
+        self.show_cursor();
+        self.flush();
     }
 
     fn newline(&mut self) {
-        self.y_pos += font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
+        self.y_pos += self.line_height();
         self.carriage_return()
     }
 
     fn carriage_return(&mut self) {
-        self.x_pos = BORDER_PADDING;
+        self.x_pos = self.text_left();
     }
 
     /// Erases all text on the screen. Resets self.x_pos and self.y_pos.
     pub fn clear(&mut self) {
-        self.x_pos = BORDER_PADDING;
-        self.y_pos = BORDER_PADDING;
-        self.framebuffer.as_mut().unwrap().fill(0);
+        self.x_pos = self.text_left();
+        self.y_pos = self.text_top();
+        let background = self.attributes.background;
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            scrollback.clear_screen(self.attributes);
+        }
+        let region = self.text_region;
+        if !self.hidden && background == Color::new(0, 0, 0) && region == Rect::new(0, 0, self.width(), self.height()) {
+            self.pixels().fill(0); //fast path
+            self.mark_all_dirty();
+        } else {
+            self.fill_rect(region.x, region.y, region.width, region.height, background);
+        }
+        if !self.flush_deferred {
+            self.flush();
+        }
+    }
+
+    /// Moves all text up by one line and clears the freed bottom line. Moves self.y_pos up with it.
+    fn scroll_up(&mut self) {
+        let line_height = self.line_height();
+        let region = self.text_region;
+        if !self.fits_a_line() {
+            //screen too small to hold even one line, so nothing to keep
+            self.clear();
+            return;
+        }
+        let info = self.info;
+        if self.hidden {
+            //not on screen, only the cells are scrolled
+        } else if let (Some(back_buffer), Some(framebuffer)) = (self.back_buffer.as_mut(), self.framebuffer.as_mut()) {
+            //the framebuffer is scrolled the same way, so that the next flush only copies the new line
+            //instead of the whole region. What is not flushed yet would end up in the wrong place, so flush first
+            self.pointer.erase(framebuffer, &info);
+            back_buffer.flush(framebuffer);
+            scroll_pixels(back_buffer.pixels_mut(), &info, region, line_height);
+            scroll_pixels(framebuffer, &info, region, line_height);
+            self.pointer.draw(framebuffer, &info);
+        } else {
+            scroll_pixels(self.pixels(), &info, region, line_height);
+        }
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            scrollback.scroll_up(self.attributes);
+        }
+        let background = self.attributes.background;
+        self.fill_rect(region.x, region.bottom() - line_height, region.width, line_height, background);
+        self.y_pos = self.y_pos.saturating_sub(line_height);
     }
 
     fn width(&self) -> usize {
@@ -113,30 +563,57 @@ impl FrameBufferWriter {
         self.info.height
     }
 
+    /// x of the first text column.
+    fn text_left(&self) -> usize {
+        self.text_region.x + BORDER_PADDING
+    }
+
+    /// y of the first text line.
+    fn text_top(&self) -> usize {
+        self.text_region.y + BORDER_PADDING
+    }
+
+    /// Number of chars that fit in a line of the text region.
+    fn text_columns(&self) -> usize {
+        self.text_region.width.saturating_sub(2 * BORDER_PADDING) / self.char_width()
+    }
+
+    /// False if the text region is too small for even one line, in which case nothing is printed.
+    fn fits_a_line(&self) -> bool {
+        self.line_height() + BORDER_PADDING < self.text_region.height
+    }
+
+    /// Number of lines that fit in the text region.
+    fn text_rows(&self) -> usize {
+        self.text_region.height.saturating_sub(2 * BORDER_PADDING) / self.line_height()
+    }
+
     fn backspace(&mut self) {
-        let new_xpos = self.x_pos - font_constants::CHAR_RASTER_WIDTH;
+        //x_pos can be left of the text, e.g. after set_x_y_pos or set_text_region, so never go past text_left
+        let new_xpos = self.x_pos.saturating_sub(self.char_raster_width()).max(self.text_left());
 
-        if new_xpos <= BORDER_PADDING {
+        if new_xpos <= self.text_left() {
             //left border
             //first clear position before moving up in y axis
             self.x_pos = new_xpos;
-            self.write_rendered_char(get_char_raster(' '));
+            self.put_char(' ');
             //Move y up if not first line
-            if self.y_pos > font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING + BORDER_PADDING
+            if self.y_pos > self.line_height() + self.text_top()
             {
                 //not first line
-                self.y_pos -= font_constants::CHAR_RASTER_HEIGHT.val() + LINE_SPACING;
+                self.y_pos -= self.line_height();
             }
             //move x to the right end
-            self.x_pos = self.width() - font_constants::CHAR_RASTER_WIDTH - BORDER_PADDING;
+            let right_end = self.text_region.right().saturating_sub(self.char_raster_width() + BORDER_PADDING);
+            self.x_pos = right_end.max(self.text_left());
         } else {
             //simply move x one step to the left
-            self.x_pos = self.x_pos - font_constants::CHAR_RASTER_WIDTH;
+            self.x_pos = new_xpos;
         }
 
         //clear
-        self.write_rendered_char(get_char_raster(' '));
-        self.x_pos = self.x_pos - font_constants::CHAR_RASTER_WIDTH;
+        self.put_char(' ');
+        self.x_pos = self.x_pos.saturating_sub(self.char_raster_width()).max(self.text_left());
     }
 
     /// Writes a single char to the framebuffer. Takes care of special control characters, such as
@@ -147,49 +624,376 @@ impl FrameBufferWriter {
             '\n' => self.newline(),
             '\r' => self.carriage_return(),
             c => {
-                let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
-                if new_xpos >= self.width() {
+                let new_xpos = self.x_pos + self.char_raster_width();
+                if new_xpos >= self.text_region.right() {
                     self.newline();
                 }
-                let new_ypos =
-                    self.y_pos + font_constants::CHAR_RASTER_HEIGHT.val() + BORDER_PADDING;
-                if new_ypos >= self.height() {
-                    self.clear();
+                if !self.fits_a_line() {
+                    return; //scrolling could never make room, e.g. for a text region shorter than a line
+                }
+                //scroll until the char fits. More than once only if set_x_y_pos moved us far down
+                while self.y_pos + self.char_raster_height() + BORDER_PADDING >= self.text_region.bottom() {
+                    self.scroll_up();
                 }
-                self.write_rendered_char(get_char_raster(c));
+                self.put_char(c);
             }
         }
     }
 
-    /// Prints a rendered char into the framebuffer.
+    /// Draws c at the current position, records it in the scrollback and advances self.x_pos.
+    fn put_char(&mut self, c: char) {
+        let (column, row) = self.current_cell();
+        let attributes = self.attributes;
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            scrollback.set(column, row, Cell { c, attributes });
+        }
+        self.write_glyph(self.get_glyph(c));
+    }
+
+    /// Prints a glyph into the framebuffer, filling the whole char cell.
     /// Updates self.x_pos.
-    fn write_rendered_char(&mut self, rendered_char: RasterizedChar) {
-        for (y, row) in rendered_char.raster().iter().enumerate() {
-            for (x, byte) in row.iter().enumerate() {
-                self.write_pixel(self.x_pos + x, self.y_pos + y, *byte);
+    fn write_glyph(&mut self, glyph: Glyph) {
+        let bold = self.attributes.bold;
+        let (cell_width, cell_height) = (self.char_raster_width(), self.char_raster_height());
+        //glyphs of fallback fonts can have another size than the cell
+        let x_offset = cell_width.saturating_sub(glyph.width()) / 2;
+        let y_offset = cell_height.saturating_sub(glyph.height()) / 2;
+        let intensity_at = |x: usize, y: usize| {
+            if x >= x_offset && x - x_offset < glyph.width() && y >= y_offset && y - y_offset < glyph.height() {
+                glyph.intensity(x - x_offset, y - y_offset)
+            } else {
+                0
+            }
+        };
+        if !self.hidden {
+            for y in 0..cell_height {
+                for x in 0..cell_width {
+                    let mut intensity = intensity_at(x, y);
+                    if bold && x > 0 {
+                        //fake bold: overstrike the glyph shifted one pixel to the right
+                        intensity = intensity.max(intensity_at(x - 1, y));
+                    }
+                    self.write_pixel(self.x_pos + x, self.y_pos + y, intensity);
+                }
             }
+            self.mark_dirty(self.x_pos, self.y_pos, cell_width, cell_height);
         }
-        self.x_pos += rendered_char.width() + LETTER_SPACING;
+        self.x_pos += cell_width + LETTER_SPACING;
+    }
+
+    /// Sets the colors of the text written from now on. Same as the SGR escape sequences.
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.attributes.foreground = foreground;
+        self.attributes.background = background;
+    }
+
+    /// Returns the current (foreground, background) colors.
+    pub fn color(&self) -> (Color, Color) {
+        (self.attributes.foreground, self.attributes.background)
     }
 
+    /// Writes a pixel of a glyph. The intensity from the font rasterizer is used as alpha,
+    /// so that the anti-aliased edges blend smoothly from the background into the foreground.
     fn write_pixel(&mut self, x: usize, y: usize, intensity: u8) {
         let pixel_offset = y * self.info.stride + x;
-        let color = match self.info.pixel_format {
-            PixelFormat::Rgb => [intensity, intensity, intensity / 2, 0],
-            PixelFormat::Bgr => [intensity / 2, intensity, intensity, 0],
-            PixelFormat::U8 => [if intensity > 200 { 0xf } else { 0 }, 0, 0, 0],
-            other => {
+        let color = self.attributes.background.blend(self.attributes.foreground, intensity);
+        let color = self.pixel_bytes(color);
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let byte_offset = pixel_offset * bytes_per_pixel;
+        self.pixels()[byte_offset..(byte_offset + bytes_per_pixel)]
+            .copy_from_slice(&color[..bytes_per_pixel]);
+        if self.back_buffer.is_none() {
+            let _ = unsafe { ptr::read_volatile(&self.framebuffer.as_ref().unwrap()[byte_offset]) };
+        }
+    }
+
+    /// Returns the bytes of a pixel of the given color in the pixel format of the framebuffer.
+    fn pixel_bytes(&mut self, color: Color) -> [u8; 4] {
+        match color.to_pixel(self.info.pixel_format) {
+            Some(bytes) => bytes,
+            None => {
+                let other = self.info.pixel_format;
                 // set a supported (but invalid) pixel format before panicking to avoid a double
                 // panic; it might not be readable though
                 self.info.pixel_format = PixelFormat::Rgb;
                 panic!("pixel format {:?} not supported in logger", other)
             }
-        };
+        }
+    }
+
+    /// Fills the given rectangle, clipped to the screen, with one color.
+    fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        let x_end = (x + width).min(self.width());
+        let y_end = (y + height).min(self.height());
+        if self.hidden || x >= x_end || y >= y_end {
+            return;
+        }
+        let pixel = self.pixel_bytes(color);
         let bytes_per_pixel = self.info.bytes_per_pixel;
-        let byte_offset = pixel_offset * bytes_per_pixel;
-        self.framebuffer.as_mut().unwrap()[byte_offset..(byte_offset + bytes_per_pixel)]
-            .copy_from_slice(&color[..bytes_per_pixel]);
-        let _ = unsafe { ptr::read_volatile(&self.framebuffer.as_ref().unwrap()[byte_offset]) };
+        let stride = self.info.stride;
+        let pixels = self.pixels();
+        for row in y..y_end {
+            let row_start = (row * stride + x) * bytes_per_pixel;
+            let row_end = (row * stride + x_end) * bytes_per_pixel;
+            for pixel_bytes in pixels[row_start..row_end].chunks_exact_mut(bytes_per_pixel) {
+                pixel_bytes.copy_from_slice(&pixel[..bytes_per_pixel]);
+            }
+        }
+        self.mark_dirty(x, y, x_end - x, y_end - y);
+    }
+
+    fn char_width(&self) -> usize {
+        self.char_raster_width() + LETTER_SPACING
+    }
+
+    fn line_height(&self) -> usize {
+        self.char_raster_height() + LINE_SPACING
+    }
+
+    /// Inverts the pixels of the cursor bar under the current position. Doing it twice restores them,
+    /// so the cursor never destroys what is under it.
+    fn invert_cursor(&mut self) {
+        let y_start = self.y_pos + self.char_raster_height() - CURSOR_HEIGHT;
+        let y_end = (y_start + CURSOR_HEIGHT).min(self.height());
+        let x_end = (self.x_pos + self.char_raster_width()).min(self.width());
+        if self.x_pos >= x_end {
+            return;
+        }
+        let bytes_per_pixel = self.info.bytes_per_pixel;
+        let stride = self.info.stride;
+        let x_start = self.x_pos;
+        let pixels = self.pixels();
+        for row in y_start..y_end {
+            let row_start = (row * stride + x_start) * bytes_per_pixel;
+            let row_end = (row * stride + x_end) * bytes_per_pixel;
+            for byte in &mut pixels[row_start..row_end] {
+                *byte = !*byte;
+            }
+        }
+        self.mark_dirty(x_start, y_start, x_end - x_start, y_end - y_start);
+    }
+
+    fn show_cursor(&mut self) {
+        if self.cursor_visible && !self.cursor_drawn && !self.hidden && self.framebuffer.is_some() {
+            self.invert_cursor();
+            self.cursor_drawn = true;
+        }
+    }
+
+    fn hide_cursor(&mut self) {
+        if self.cursor_drawn {
+            self.invert_cursor();
+            self.cursor_drawn = false;
+        }
+    }
+
+    /// Moves to the given text cell, 0 based, clamped to the screen.
+    fn move_to_cell(&mut self, column: usize, row: usize) {
+        let max_column = self.text_columns();
+        let max_row = self.text_rows();
+        self.x_pos = self.text_left() + column.min(max_column.saturating_sub(1)) * self.char_width();
+        self.y_pos = self.text_top() + row.min(max_row.saturating_sub(1)) * self.line_height();
+    }
+
+    /// Returns the current text cell (column, row), 0 based.
+    fn current_cell(&self) -> (usize, usize) {
+        (
+            self.x_pos.saturating_sub(self.text_left()) / self.char_width(),
+            self.y_pos.saturating_sub(self.text_top()) / self.line_height(),
+        )
+    }
+
+    /// Handles a two char escape sequence.
+    fn handle_esc(&mut self, c: char) {
+        match c {
+            '7' => self.save_cursor(),
+            '8' => self.restore_cursor(),
+            'c' => {
+                //full reset
+                self.attributes = Attributes::new();
+                self.cursor_visible = true;
+                self.clear();
+            }
+            _ => {} //not supported
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursor = (self.x_pos, self.y_pos, self.attributes);
+    }
+
+    fn restore_cursor(&mut self) {
+        (self.x_pos, self.y_pos, self.attributes) = self.saved_cursor;
+    }
+
+    /// Handles a control sequence (ESC [ ...).
+    fn handle_csi(&mut self, sequence: &CsiSequence) {
+        if sequence.private {
+            //DECTCEM: ESC [ ? 25 h shows and ESC [ ? 25 l hides the cursor
+            if sequence.param_or(0, 0) == 25 {
+                match sequence.final_char {
+                    'h' => self.cursor_visible = true,
+                    'l' => self.cursor_visible = false,
+                    _ => {}
+                }
+            }
+            return;
+        }
+
+        let (column, row) = self.current_cell();
+        let n = sequence.param_or(0, 1) as usize;
+        match sequence.final_char {
+            'A' => self.move_to_cell(column, row.saturating_sub(n)), //CUU
+            'B' => self.move_to_cell(column, row + n), //CUD
+            'C' => self.move_to_cell(column + n, row), //CUF
+            'D' => self.move_to_cell(column.saturating_sub(n), row), //CUB
+            'H' | 'f' => {
+                //CUP: ESC [ row ; column H, 1 based
+                let row = sequence.param_or(0, 1) as usize - 1;
+                let column = sequence.param_or(1, 1) as usize - 1;
+                self.move_to_cell(column, row);
+            }
+            'K' => self.erase_in_line(sequence.params().first().copied().unwrap_or(0)), //EL
+            'J' => self.erase_in_display(sequence.params().first().copied().unwrap_or(0)), //ED
+            'm' => self.select_graphic_rendition(sequence.params()), //SGR
+            's' => self.save_cursor(),
+            'u' => self.restore_cursor(),
+            _ => {} //not supported
+        }
+    }
+
+    /// EL. 0: from the cursor to the end of the line, 1: from the start of the line to the cursor,
+    /// 2: the whole line. The cursor does not move.
+    fn erase_in_line(&mut self, mode: u16) {
+        let region = self.text_region;
+        let (x_start, x_end) = match mode {
+            0 => (self.x_pos, region.right()),
+            1 => (region.x, self.x_pos + self.char_width()),
+            2 => (region.x, region.right()),
+            _ => return,
+        };
+        let (_, row) = self.current_cell();
+        let attributes = self.attributes;
+        let start = x_start.saturating_sub(self.text_left()) / self.char_width();
+        let end = x_end.saturating_sub(self.text_left()).div_ceil(self.char_width());
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            scrollback.erase(row, start, end, attributes);
+        }
+        self.fill_rect(x_start, self.y_pos, x_end - x_start, self.line_height(), attributes.background);
+    }
+
+    /// ED. 0: from the cursor to the end of the screen, 1: from the start of the screen to the cursor,
+    /// 2 and 3: the whole screen. The cursor does not move.
+    fn erase_in_display(&mut self, mode: u16) {
+        let background = self.attributes.background;
+        let region = self.text_region;
+        let line_end = self.y_pos + self.line_height();
+        let (_, row) = self.current_cell();
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                self.erase_rows(row + 1, usize::MAX);
+                let height = region.bottom().saturating_sub(line_end);
+                self.fill_rect(region.x, line_end, region.width, height, background);
+            }
+            1 => {
+                self.erase_rows(0, row);
+                let height = self.y_pos.saturating_sub(region.y);
+                self.fill_rect(region.x, region.y, region.width, height, background);
+                self.erase_in_line(1);
+            }
+            2 | 3 => {
+                self.erase_rows(0, usize::MAX);
+                self.fill_rect(region.x, region.y, region.width, region.height, background);
+            }
+            _ => {}
+        }
+    }
+
+    /// Records erasing the rows from start up to (excluding) end in the scrollback.
+    fn erase_rows(&mut self, start: usize, end: usize) {
+        let attributes = self.attributes;
+        if let Some(scrollback) = self.scrollback.as_mut() {
+            for row in start..end.min(scrollback.rows()) {
+                scrollback.erase(row, 0, usize::MAX, attributes);
+            }
+        }
+    }
+
+    /// SGR. Sets colors and boldness. No parameters means reset, as for ESC [ m
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        if params.is_empty() {
+            self.attributes = Attributes::new();
+            return;
+        }
+        let mut i = 0;
+        while i < params.len() {
+            match params[i] {
+                0 => self.attributes = Attributes::new(),
+                1 => self.attributes.bold = true,
+                22 => self.attributes.bold = false,
+                code @ 30..=37 => self.attributes.foreground = Color::from_ansi_256((code - 30) as u8),
+                39 => self.attributes.foreground = DEFAULT_FOREGROUND,
+                code @ 40..=47 => self.attributes.background = Color::from_ansi_256((code - 40) as u8),
+                49 => self.attributes.background = DEFAULT_BACKGROUND,
+                code @ 90..=97 => self.attributes.foreground = Color::from_ansi_256((code - 90 + 8) as u8),
+                code @ 100..=107 => self.attributes.background = Color::from_ansi_256((code - 100 + 8) as u8),
+                code @ (38 | 48) => {
+                    //extended colors: 38;5;n (256 colors) or 38;2;r;g;b (24 bit). 48 for the background
+                    let color = match params.get(i + 1) {
+                        Some(5) => {
+                            let color = params.get(i + 2).map(|&n| Color::from_ansi_256(n as u8));
+                            i += 2;
+                            color
+                        }
+                        Some(2) => {
+                            let channel = |j: usize| params.get(i + j).map(|&v| v as u8);
+                            let color = match (channel(2), channel(3), channel(4)) {
+                                (Some(r), Some(g), Some(b)) => Some(Color::new(r, g, b)),
+                                _ => None,
+                            };
+                            i += 4;
+                            color
+                        }
+                        _ => None,
+                    };
+                    if let Some(color) = color {
+                        if code == 38 {
+                            self.attributes.foreground = color;
+                        } else {
+                            self.attributes.background = color;
+                        }
+                    }
+                }
+                _ => {} //not supported
+            }
+            i += 1;
+        }
+    }
+}
+
+//font selection API. The kernel itself uses the automatic choice of init
+#[allow(dead_code)]
+impl FrameBufferWriter {
+    /// Changes the font size and weight. The raster height is chosen automatically by init,
+    /// depending on the resolution. Clears the text region, empties the scrollback and wipes all the
+    /// other virtual terminals, as the text would not fit the new char size anymore.
+    pub fn set_font(&mut self, raster_height: RasterHeight, font_weight: FontWeight) {
+        self.hide_cursor();
+        self.raster_height = raster_height;
+        self.font_weight = font_weight;
+        self.reset_cells();
+        if self.back_buffer.is_some() {
+            self.flush(); //the new back buffer starts as a copy of the framebuffer
+            self.init_back_buffer(); //its cells have the char size
+        }
+        self.clear();
+        self.show_cursor();
+        self.flush();
+    }
+
+    pub fn font(&self) -> (RasterHeight, FontWeight) {
+        (self.raster_height, self.font_weight)
     }
 }
 
@@ -198,9 +1002,49 @@ unsafe impl Sync for FrameBufferWriter {}
 
 impl fmt::Write for FrameBufferWriter {
     fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.snap_back(); //new output is always written to, and shown on, the live screen
+        self.hide_cursor();
         for c in s.chars() {
-            self.write_char(c);
+            match self.ansi_parser.advance(c) {
+                Action::Print(c) => self.write_char(c),
+                Action::Csi(sequence) => self.handle_csi(&sequence),
+                Action::Esc(c) => self.handle_esc(c),
+                Action::None => {}
+            }
+        }
+        self.show_cursor();
+        if !self.flush_deferred {
+            self.flush();
         }
         Ok(())
     }
+
+    /// Flushes once for the whole formatted output, instead of once per piece of it.
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.flush_deferred = true;
+        let result = fmt::write(self, args);
+        self.flush_deferred = false;
+        self.flush();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::fmt::Write;
+
+    #[test_case]
+    fn text_region_shorter_than_a_line_drops_text() {
+        //hidden, so that nothing is drawn into the missing framebuffer
+        let mut writer = FrameBufferWriter::empty();
+        writer.hidden = true;
+        writer.info.width = 640;
+        writer.info.height = 480;
+        let line_height = writer.line_height();
+        writer.set_text_region(Rect::new(0, 100, 640, line_height));
+        writer.write_str("a\nb").unwrap(); //used to scroll forever
+        writer.set_text_region(Rect::new(0, 1000, 640, 100)); //off the screen, so no height at all
+        writer.write_str("c").unwrap();
+    }
 }
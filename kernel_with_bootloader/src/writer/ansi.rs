@@ -0,0 +1,223 @@
+//VT100/ANSI escape sequence parser. It only splits the char stream into printable chars and
+//escape sequences; FrameBufferWriter gives the sequences their meaning.
+//Ref: https://vt100.net/emu/dec_ansi_parser and https://en.wikipedia.org/wiki/ANSI_escape_code
+
+/// Start of every escape sequence.
+pub const ESC: char = '\u{001B}';
+
+/// Max number of parameters kept per control sequence. Further ones are ignored.
+const MAX_PARAMS: usize = 16;
+
+/// What the writer has to do for a char fed into the Parser.
+pub enum Action {
+    /// An ordinary char (or control char such as '\n') to be written.
+    Print(char),
+    /// A complete control sequence, i.e. ESC [ ... final char.
+    Csi(CsiSequence),
+    /// A complete two char escape sequence, e.g. ESC 7. Holds the char after ESC.
+    Esc(char),
+    /// The char was consumed as part of an unfinished escape sequence.
+    None,
+}
+
+pub struct CsiSequence {
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    /// Set for DEC private sequences, which start with '?' e.g. ESC [ ? 25 l
+    pub private: bool,
+    pub final_char: char,
+}
+
+impl CsiSequence {
+    /// Returns parameter i, or default if it is missing or 0, as VT100 treats 0 like a missing parameter
+    /// for most sequences.
+    pub fn param_or(&self, i: usize, default: u16) -> u16 {
+        match self.params().get(i) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.param_count]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+pub struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    private: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+            private: false,
+        }
+    }
+
+    pub fn advance(&mut self, c: char) -> Action {
+        match self.state {
+            State::Ground => {
+                if c == ESC {
+                    self.state = State::Escape;
+                    Action::None
+                } else {
+                    Action::Print(c)
+                }
+            }
+            State::Escape => match c {
+                '[' => {
+                    self.state = State::Csi;
+                    self.params = [0; MAX_PARAMS];
+                    self.param_count = 0;
+                    self.private = false;
+                    Action::None
+                }
+                ESC => Action::None, //stay in Escape, the previous ESC is dropped
+                _ => {
+                    self.state = State::Ground;
+                    Action::Esc(c)
+                }
+            },
+            State::Csi => self.advance_csi(c),
+        }
+    }
+
+    fn advance_csi(&mut self, c: char) -> Action {
+        match c {
+            '0'..='9' => {
+                if self.param_count == 0 {
+                    self.param_count = 1;
+                }
+                //no slot if there are more than MAX_PARAMS parameters
+                if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add(c as u16 - '0' as u16);
+                }
+                Action::None
+            }
+            ';' => {
+                if self.param_count == 0 {
+                    self.param_count = 1; //the first parameter was left out, e.g. ESC [ ; 5 H
+                }
+                //counts one past MAX_PARAMS at most, meaning the digits that follow are ignored
+                if self.param_count <= MAX_PARAMS {
+                    self.param_count += 1;
+                }
+                Action::None
+            }
+            '?' => {
+                self.private = true;
+                Action::None
+            }
+            //intermediate bytes. None of the sequences we support use them
+            ' '..='/' => Action::None,
+            //final byte
+            '@'..='~' => {
+                self.state = State::Ground;
+                Action::Csi(CsiSequence {
+                    params: self.params,
+                    param_count: self.param_count.min(MAX_PARAMS),
+                    private: self.private,
+                    final_char: c,
+                })
+            }
+            ESC => {
+                //a new sequence starts before this one finished. Drop this one
+                self.state = State::Escape;
+                Action::None
+            }
+            _ => {
+                //not valid inside a control sequence. Abort it and print the char
+                self.state = State::Ground;
+                Action::Print(c)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::{Color, FrameBufferWriter};
+    use core::fmt::Write;
+
+    /// Feeds s and returns the last complete control sequence.
+    fn last_csi(parser: &mut Parser, s: &str) -> Option<CsiSequence> {
+        s.chars().fold(None, |last, c| match parser.advance(c) {
+            Action::Csi(sequence) => Some(sequence),
+            _ => last,
+        })
+    }
+
+    /// A writer that draws nothing, so that only its state changes. Only feed it escape sequences.
+    fn hidden_writer() -> FrameBufferWriter {
+        let mut writer = FrameBufferWriter::empty();
+        writer.hidden = true;
+        writer
+    }
+
+    #[test_case]
+    fn params_can_be_split_across_write_str_calls() {
+        let mut writer = hidden_writer();
+        writer.write_str("\x1b[38;2;1").unwrap();
+        writer.write_str("0;20;30m").unwrap();
+        assert_eq!(writer.attributes.foreground, Color::new(10, 20, 30));
+    }
+
+    #[test_case]
+    fn extended_colors_are_selected() {
+        let mut writer = hidden_writer();
+        writer.write_str("\x1b[38;5;196m\x1b[48;2;1;2;3m").unwrap();
+        assert_eq!(writer.attributes.foreground, Color::from_ansi_256(196));
+        assert_eq!(writer.attributes.background, Color::new(1, 2, 3));
+    }
+
+    #[test_case]
+    fn cursor_can_be_hidden_and_shown() {
+        let mut writer = hidden_writer();
+        writer.write_str("\x1b[?25l").unwrap();
+        assert!(!writer.cursor_visible);
+        writer.write_str("\x1b[?25h").unwrap();
+        assert!(writer.cursor_visible);
+    }
+
+    #[test_case]
+    fn unknown_final_bytes_end_the_sequence() {
+        let mut parser = Parser::new();
+        let sequence = last_csi(&mut parser, "\x1b[1;31z").unwrap();
+        assert_eq!((sequence.final_char, sequence.params()), ('z', &[1, 31][..]));
+        assert!(matches!(parser.advance('a'), Action::Print('a')));
+
+        let mut writer = hidden_writer();
+        let before = writer.attributes.foreground;
+        writer.write_str("\x1b[1;31z").unwrap();
+        assert_eq!(writer.attributes.foreground, before);
+    }
+
+    #[test_case]
+    fn overlong_parameter_lists_are_cut() {
+        let mut parser = Parser::new();
+        let mut s = alloc::string::String::from("\x1b[");
+        for _ in 0..MAX_PARAMS + 4 {
+            s.push_str("7;");
+        }
+        s.push('m');
+        let sequence = last_csi(&mut parser, &s).unwrap();
+        assert_eq!(sequence.params().len(), MAX_PARAMS);
+        assert!(sequence.params().iter().all(|&param| param == 7));
+    }
+}
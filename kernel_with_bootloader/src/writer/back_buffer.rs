@@ -0,0 +1,140 @@
+//Off-screen copy of the framebuffer. Drawing into normal memory is much faster than into the
+//framebuffer, especially under QEMU TCG, so the writer draws here and flush only copies the cells
+//that changed since the last flush to the framebuffer.
+//The cells are the text cells of the writer: cell_width x cell_height pixels starting at the border.
+//The first and last cell of a row or column also cover the border.
+
+use alloc::{vec, vec::Vec};
+
+use bootloader_api::info::FrameBufferInfo;
+
+pub struct BackBuffer {
+    pixels: Vec<u8>, //same layout as the framebuffer, up to the last visible row
+    row_bytes: usize,
+    bytes_per_pixel: usize,
+    width: usize,
+    height: usize,
+    cell_width: usize,
+    cell_height: usize,
+    border: usize,
+    columns: usize,
+    rows: usize,
+    dirty: Vec<bool>, //one per cell, row by row
+    any_dirty: bool,
+    all_dirty: bool, //e.g. after clearing the screen. Lets flush copy the whole screen at once
+}
+
+impl BackBuffer {
+    /// Creates a back buffer with the current content of the framebuffer.
+    pub fn new(
+        framebuffer: &[u8],
+        info: &FrameBufferInfo,
+        cell_width: usize,
+        cell_height: usize,
+        border: usize,
+    ) -> Self {
+        let row_bytes = info.stride * info.bytes_per_pixel;
+        let columns = info.width.saturating_sub(border).div_ceil(cell_width).max(1);
+        let rows = info.height.saturating_sub(border).div_ceil(cell_height).max(1);
+        BackBuffer {
+            pixels: framebuffer[..info.height * row_bytes].to_vec(),
+            row_bytes,
+            bytes_per_pixel: info.bytes_per_pixel,
+            width: info.width,
+            height: info.height,
+            cell_width,
+            cell_height,
+            border,
+            columns,
+            rows,
+            dirty: vec![false; columns * rows],
+            any_dirty: false,
+            all_dirty: false,
+        }
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    /// Marks the cells overlapping the given rectangle of pixels as changed.
+    pub fn mark_dirty(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        if width == 0 || height == 0 || self.all_dirty {
+            return;
+        }
+        let first_column = cell_of(x, self.border, self.cell_width, self.columns);
+        let last_column = cell_of(x + width - 1, self.border, self.cell_width, self.columns);
+        let first_row = cell_of(y, self.border, self.cell_height, self.rows);
+        let last_row = cell_of(y + height - 1, self.border, self.cell_height, self.rows);
+        for row in first_row..=last_row {
+            let start = row * self.columns;
+            self.dirty[start + first_column..=start + last_column].fill(true);
+        }
+        self.any_dirty = true;
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.all_dirty = true;
+        self.any_dirty = true;
+    }
+
+    /// Copies the changed cells to the framebuffer. Neighbouring changed cells of a row are copied
+    /// together, one pixel row at a time.
+    pub fn flush(&mut self, framebuffer: &mut [u8]) {
+        if !self.any_dirty {
+            return;
+        }
+        if self.all_dirty {
+            framebuffer[..self.pixels.len()].copy_from_slice(&self.pixels);
+        } else {
+            for row in 0..self.rows {
+                let y_start = cell_start(row, self.border, self.cell_height);
+                let y_end = cell_end(row, self.border, self.cell_height, self.rows, self.height);
+                let mut column = 0;
+                while column < self.columns {
+                    if !self.dirty[row * self.columns + column] {
+                        column += 1;
+                        continue;
+                    }
+                    let first_column = column;
+                    while column < self.columns && self.dirty[row * self.columns + column] {
+                        column += 1;
+                    }
+                    let x_start = cell_start(first_column, self.border, self.cell_width);
+                    let x_end = cell_end(column - 1, self.border, self.cell_width, self.columns, self.width);
+                    for y in y_start..y_end {
+                        let start = y * self.row_bytes + x_start * self.bytes_per_pixel;
+                        let end = y * self.row_bytes + x_end * self.bytes_per_pixel;
+                        framebuffer[start..end].copy_from_slice(&self.pixels[start..end]);
+                    }
+                }
+            }
+        }
+        self.dirty.fill(false);
+        self.any_dirty = false;
+        self.all_dirty = false;
+    }
+}
+
+/// Returns the cell that contains the pixel at pos along one axis.
+fn cell_of(pos: usize, border: usize, cell_size: usize, count: usize) -> usize {
+    (pos.saturating_sub(border) / cell_size).min(count - 1)
+}
+
+/// Returns the first pixel of a cell along one axis.
+fn cell_start(cell: usize, border: usize, cell_size: usize) -> usize {
+    if cell == 0 {
+        0
+    } else {
+        border + cell * cell_size
+    }
+}
+
+/// Returns the pixel after the last one of a cell along one axis.
+fn cell_end(cell: usize, border: usize, cell_size: usize, count: usize, limit: usize) -> usize {
+    if cell == count - 1 {
+        limit
+    } else {
+        border + (cell + 1) * cell_size
+    }
+}
\ No newline at end of file
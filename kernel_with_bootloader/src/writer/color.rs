@@ -0,0 +1,145 @@
+use bootloader_api::info::PixelFormat;
+
+/// An RGB color, independent of the pixel format of the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+//named colors for set_color and print_colored!, not all of them are used by the kernel itself
+#[allow(dead_code)]
+impl Color {
+    pub const BLACK: Color = ANSI_COLORS[0];
+    pub const RED: Color = ANSI_COLORS[9];
+    pub const GREEN: Color = ANSI_COLORS[10];
+    pub const YELLOW: Color = ANSI_COLORS[11];
+    pub const BLUE: Color = ANSI_COLORS[12];
+    pub const MAGENTA: Color = ANSI_COLORS[13];
+    pub const CYAN: Color = ANSI_COLORS[14];
+    pub const WHITE: Color = ANSI_COLORS[15];
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+
+    /// Returns the color of the given index of the xterm 256-color palette.
+    /// 0..16 are the 16 ANSI colors, 16..232 a 6x6x6 color cube and 232..256 a gray ramp.
+    pub fn from_ansi_256(index: u8) -> Self {
+        match index {
+            0..=15 => ANSI_COLORS[index as usize],
+            16..=231 => {
+                let index = index - 16;
+                Color::new(
+                    CUBE_LEVELS[(index / 36) as usize],
+                    CUBE_LEVELS[(index / 6 % 6) as usize],
+                    CUBE_LEVELS[(index % 6) as usize],
+                )
+            }
+            232..=255 => {
+                let level = 8 + 10 * (index - 232);
+                Color::new(level, level, level)
+            }
+        }
+    }
+
+    /// Blends this color with other. alpha 0 gives this color, 255 gives other.
+    pub fn blend(self, other: Color, alpha: u8) -> Self {
+        let mix = |from: u8, to: u8| {
+            let alpha = alpha as u16;
+            ((from as u16 * (255 - alpha) + to as u16 * alpha + 127) / 255) as u8
+        };
+        Color::new(mix(self.r, other.r), mix(self.g, other.g), mix(self.b, other.b))
+    }
+
+    /// Returns the bytes of a pixel of this color in the given pixel format, None if the format is not supported.
+    /// Only the first bytes_per_pixel bytes are used.
+    pub fn to_pixel(self, pixel_format: PixelFormat) -> Option<[u8; 4]> {
+        match pixel_format {
+            PixelFormat::Rgb => Some([self.r, self.g, self.b, 0]),
+            PixelFormat::Bgr => Some([self.b, self.g, self.r, 0]),
+            PixelFormat::U8 => Some([self.luminance(), 0, 0, 0]), //grayscale
+            PixelFormat::Unknown {
+                red_position,
+                green_position,
+                blue_position,
+            } => {
+                //the positions are the bit offsets of the 8 bit channels inside the pixel
+                let pixel = (self.r as u32) << red_position
+                    | (self.g as u32) << green_position
+                    | (self.b as u32) << blue_position;
+                Some(pixel.to_le_bytes())
+            }
+            _ => None,
+        }
+    }
+
+    /// The reverse of to_pixel: reads the color of a pixel of the given format.
+    pub fn from_pixel(bytes: &[u8], pixel_format: PixelFormat) -> Option<Self> {
+        match pixel_format {
+            PixelFormat::Rgb => Some(Color::new(bytes[0], bytes[1], bytes[2])),
+            PixelFormat::Bgr => Some(Color::new(bytes[2], bytes[1], bytes[0])),
+            PixelFormat::U8 => Some(Color::new(bytes[0], bytes[0], bytes[0])),
+            PixelFormat::Unknown {
+                red_position,
+                green_position,
+                blue_position,
+            } => {
+                let mut pixel = [0; 4];
+                let len = bytes.len().min(4);
+                pixel[..len].copy_from_slice(&bytes[..len]);
+                let pixel = u32::from_le_bytes(pixel);
+                let channel = |position: u8| (pixel >> position) as u8;
+                Some(Color::new(channel(red_position), channel(green_position), channel(blue_position)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the closest of the 16 ANSI colors, e.g. for text modes with few colors.
+    pub fn to_ansi_16(self) -> u8 {
+        let distance = |other: &Color| {
+            let square = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+            square(self.r, other.r) + square(self.g, other.g) + square(self.b, other.b)
+        };
+        let (index, _) = ANSI_COLORS.iter().enumerate().min_by_key(|(_, color)| distance(color)).unwrap();
+        index as u8
+    }
+
+    /// Perceived brightness from 0 to 255.
+    pub fn luminance(&self) -> u8 {
+        ((self.r as u16 * 3 + self.g as u16 * 6 + self.b as u16) / 10) as u8
+    }
+}
+
+/// The 16 ANSI colors as rendered by xterm: black, red, green, yellow, blue, magenta, cyan, white,
+/// followed by their bright variants.
+pub const ANSI_COLORS: [Color; 16] = [
+    Color::new(0, 0, 0),
+    Color::new(205, 0, 0),
+    Color::new(0, 205, 0),
+    Color::new(205, 205, 0),
+    Color::new(0, 0, 238),
+    Color::new(205, 0, 205),
+    Color::new(0, 205, 205),
+    Color::new(229, 229, 229),
+    Color::new(127, 127, 127),
+    Color::new(255, 0, 0),
+    Color::new(0, 255, 0),
+    Color::new(255, 255, 0),
+    Color::new(92, 92, 255),
+    Color::new(255, 0, 255),
+    Color::new(0, 255, 255),
+    Color::new(255, 255, 255),
+];
+
+/// Intensities of the 6 steps of each channel in the 256-color cube.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The yellowish text color the writer has always used.
+pub const DEFAULT_FOREGROUND: Color = Color::new(255, 255, 127);
+
+pub const DEFAULT_BACKGROUND: Color = Color::new(0, 0, 0);
\ No newline at end of file
@@ -1,23 +1,29 @@
-use noto_sans_mono_bitmap::{
-    get_raster_width, FontWeight, RasterHeight
-};
+use noto_sans_mono_bitmap::{FontWeight, RasterHeight};
 
 /// Constants for the usage of the [noto_sans_mono_bitmap] crate.
 pub mod font_constants {
     use super::*;
 
-    /// Height of each char raster. The font size is ~0.84% of this. Thus, this is the line height that
-    /// enables multiple characters to be side-by-side and appear optically in one line in a natural way.
-    pub const CHAR_RASTER_HEIGHT: RasterHeight = RasterHeight::Size16;
+    /// Char raster heights the writer can use, smallest first. The font size is ~0.84% of the height,
+    /// so this is also the line height that makes chars side-by-side appear in one line naturally.
+    /// noto_sans_mono_bitmap has no smaller raster than 16, so there is no 14.
+    pub const RASTER_HEIGHTS: [RasterHeight; 4] = [
+        RasterHeight::Size16,
+        RasterHeight::Size20,
+        RasterHeight::Size24,
+        RasterHeight::Size32,
+    ];
 
-    /// The width of each single symbol of the mono space font.
-    pub const CHAR_RASTER_WIDTH: usize = get_raster_width(FontWeight::Regular, CHAR_RASTER_HEIGHT);
+    /// When choosing the raster height automatically, the biggest one that still fits this many chars
+    /// in a line and this many lines on the screen is used.
+    pub const MIN_COLUMNS: usize = 100;
+    pub const MIN_LINES: usize = 40;
 
     /// Backup character if a desired symbol is not available by the font.
     /// The '�' character requires the feature "unicode-specials".
     pub const BACKUP_CHAR: char = '�';
 
-    pub const FONT_WEIGHT: FontWeight = FontWeight::Regular;
+    pub const DEFAULT_FONT_WEIGHT: FontWeight = FontWeight::Regular;
 
     pub const BACKSPACE: char = '\u{0008}';
 
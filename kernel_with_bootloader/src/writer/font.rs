@@ -0,0 +1,71 @@
+//Glyph sources of the writer. The built-in noto_sans_mono_bitmap font only covers a few Unicode
+//blocks, so more fonts (e.g. PSF fonts, see psf.rs) can be added as fallbacks: a char is drawn
+//with the first font of the chain that has it.
+
+use noto_sans_mono_bitmap::{get_raster, FontWeight, RasterHeight};
+
+/// The pixels of one char.
+#[derive(Debug, Clone, Copy)]
+pub enum Glyph {
+    /// One intensity byte per pixel, 0 for background up to 255 for foreground, row by row.
+    /// Used by anti-aliased fonts such as noto_sans_mono_bitmap.
+    Intensity(&'static [&'static [u8]]),
+    /// One bit per pixel, most significant bit first, each row padded to whole bytes.
+    /// Used by bitmap fonts such as PSF.
+    Bitmap {
+        data: &'static [u8],
+        width: usize,
+        height: usize,
+    },
+}
+
+impl Glyph {
+    pub fn width(&self) -> usize {
+        match self {
+            Glyph::Intensity(raster) => raster.first().map_or(0, |row| row.len()),
+            Glyph::Bitmap { width, .. } => *width,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self {
+            Glyph::Intensity(raster) => raster.len(),
+            Glyph::Bitmap { height, .. } => *height,
+        }
+    }
+
+    /// Returns how much the pixel belongs to the char, from 0 (not at all) to 255.
+    pub fn intensity(&self, x: usize, y: usize) -> u8 {
+        match self {
+            Glyph::Intensity(raster) => raster[y][x],
+            Glyph::Bitmap { data, width, .. } => {
+                let row_bytes = width.div_ceil(8);
+                let byte = data[y * row_bytes + x / 8];
+                if byte & (0x80 >> (x % 8)) != 0 {
+                    255
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+/// A source of glyphs.
+pub trait Font: Send {
+    /// Returns the glyph of c, None if the font does not have it.
+    fn glyph(&self, c: char) -> Option<Glyph>;
+}
+
+/// The font built into the kernel, in one of the sizes and weights of noto_sans_mono_bitmap.
+#[derive(Debug, Clone, Copy)]
+pub struct NotoFont {
+    pub raster_height: RasterHeight,
+    pub font_weight: FontWeight,
+}
+
+impl Font for NotoFont {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        get_raster(c, self.font_weight, self.raster_height).map(|raster| Glyph::Intensity(raster.raster()))
+    }
+}
\ No newline at end of file
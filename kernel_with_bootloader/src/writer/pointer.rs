@@ -0,0 +1,127 @@
+//The mouse pointer. It is drawn on the framebuffer itself, over the text and graphics of the back buffer,
+//so the pixels under it are saved when it is drawn and put back when it is erased: before it moves
+//and before the back buffer is flushed, which would otherwise copy over it.
+
+use bootloader_api::info::FrameBufferInfo;
+
+use crate::graphics::{Canvas, Sprite};
+
+const WIDTH: usize = 11;
+const HEIGHT: usize = 17;
+
+//the classic arrow. X is the black outline, . the white inside
+const SHAPE: [&[u8; WIDTH]; HEIGHT] = [
+    b"X          ",
+    b"XX         ",
+    b"X.X        ",
+    b"X..X       ",
+    b"X...X      ",
+    b"X....X     ",
+    b"X.....X    ",
+    b"X......X   ",
+    b"X.......X  ",
+    b"X........X ",
+    b"X.....XXXXX",
+    b"X..X..X    ",
+    b"X.X X..X   ",
+    b"XX  X..X   ",
+    b"X    X..X  ",
+    b"     X..X  ",
+    b"      XX   ",
+];
+
+const RGBA: [u8; WIDTH * HEIGHT * 4] = rgba_of(&SHAPE);
+
+const fn rgba_of(shape: &[&[u8; WIDTH]; HEIGHT]) -> [u8; WIDTH * HEIGHT * 4] {
+    let mut rgba = [0; WIDTH * HEIGHT * 4];
+    let mut i = 0;
+    while i < WIDTH * HEIGHT {
+        let value = match shape[i / WIDTH][i % WIDTH] {
+            b'X' => 0,
+            b'.' => 255,
+            _ => {
+                i += 1;
+                continue; //transparent, alpha stays 0
+            }
+        };
+        rgba[i * 4] = value;
+        rgba[i * 4 + 1] = value;
+        rgba[i * 4 + 2] = value;
+        rgba[i * 4 + 3] = 255;
+        i += 1;
+    }
+    rgba
+}
+
+pub struct Pointer {
+    x: usize,
+    y: usize,
+    visible: bool,
+    drawn: bool,
+    under: [u8; WIDTH * HEIGHT * 4], //the framebuffer pixels it covers while drawn, at most 4 bytes each
+}
+
+impl Pointer {
+    pub const fn new() -> Self {
+        Pointer {
+            x: 0,
+            y: 0,
+            visible: false,
+            drawn: false,
+            under: [0; WIDTH * HEIGHT * 4],
+        }
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        (self.x, self.y)
+    }
+
+    /// Must be erased while it moves.
+    pub fn set_position(&mut self, x: usize, y: usize) {
+        debug_assert!(!self.drawn);
+        (self.x, self.y) = (x, y);
+    }
+
+    /// Must be erased while it is hidden.
+    pub fn set_visible(&mut self, visible: bool) {
+        debug_assert!(!self.drawn);
+        self.visible = visible;
+    }
+
+    /// Saves the pixels under the pointer, then draws it. Does nothing if it is hidden or already drawn.
+    pub fn draw(&mut self, framebuffer: &mut [u8], info: &FrameBufferInfo) {
+        if !self.visible || self.drawn {
+            return;
+        }
+        self.copy_under(framebuffer, info, true);
+        let sprite = Sprite { width: WIDTH, height: HEIGHT, rgba: &RGBA };
+        Canvas::new(framebuffer, *info).blit(self.x as isize, self.y as isize, &sprite);
+        self.drawn = true;
+    }
+
+    /// Puts back the pixels saved by draw.
+    pub fn erase(&mut self, framebuffer: &mut [u8], info: &FrameBufferInfo) {
+        if self.drawn {
+            self.copy_under(framebuffer, info, false);
+            self.drawn = false;
+        }
+    }
+
+    /// Copies the pixels under the pointer, as far as they are on the screen,
+    /// from the framebuffer to self.under if save is set, else the other way.
+    fn copy_under(&mut self, framebuffer: &mut [u8], info: &FrameBufferInfo, save: bool) {
+        let bytes_per_pixel = info.bytes_per_pixel;
+        let columns = WIDTH.min(info.width.saturating_sub(self.x));
+        let rows = HEIGHT.min(info.height.saturating_sub(self.y));
+        for row in 0..rows {
+            let start = ((self.y + row) * info.stride + self.x) * bytes_per_pixel;
+            let pixels = &mut framebuffer[start..start + columns * bytes_per_pixel];
+            let saved = &mut self.under[row * WIDTH * 4..][..columns * bytes_per_pixel];
+            if save {
+                saved.copy_from_slice(pixels);
+            } else {
+                pixels.copy_from_slice(saved);
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,252 @@
+//Parser for PC Screen Fonts, the bitmap fonts of the Linux console (e.g. /usr/share/consolefonts,
+//after gunzip). PSF1 has 256 or 512 glyphs of 8 pixels width, PSF2 any number of any size.
+//Both can have a table that maps Unicode chars to glyphs; without it glyph i is char i.
+//Ref: https://www.win.tue.nl/~aeb/linux/kbd/font-formats-1.html and
+//https://wiki.osdev.org/PC_Screen_Font
+
+use alloc::collections::BTreeMap;
+
+use super::font::{Font, Glyph};
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xFFFF;
+const PSF1_STARTSEQ: u16 = 0xFFFE;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xFF;
+const PSF2_STARTSEQ: u8 = 0xFE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfError {
+    /// Neither the PSF1 nor the PSF2 magic number, e.g. a compressed .psf.gz file.
+    BadMagic,
+    /// The data ends before the header says it should.
+    Truncated,
+}
+
+/// A PSF1 or PSF2 font. The glyphs are not copied, so the data must live forever,
+/// e.g. include_bytes! or a ramdisk.
+pub struct PsfFont {
+    glyphs: &'static [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+    unicode: Option<BTreeMap<char, usize>>, //char to glyph index, None if the font has no table
+}
+
+impl PsfFont {
+    /// Parses a PSF1 or PSF2 font. Needs the heap if the font has a Unicode table.
+    pub fn parse(data: &'static [u8]) -> Result<Self, PsfError> {
+        if data.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(data)
+        } else if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else {
+            Err(PsfError::BadMagic)
+        }
+    }
+
+    fn parse_psf1(data: &'static [u8]) -> Result<Self, PsfError> {
+        let mode = *data.get(2).ok_or(PsfError::Truncated)?;
+        let height = *data.get(3).ok_or(PsfError::Truncated)? as usize;
+        let glyph_count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+        let glyphs_end = PSF1_HEADER_SIZE + glyph_count * height;
+        let glyphs = data.get(PSF1_HEADER_SIZE..glyphs_end).ok_or(PsfError::Truncated)?;
+
+        let unicode = if mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0 {
+            let mut unicode = BTreeMap::new();
+            let mut glyph = 0;
+            let mut in_sequence = false;
+            for value in data[glyphs_end..].chunks_exact(2) {
+                match u16::from_le_bytes([value[0], value[1]]) {
+                    PSF1_SEPARATOR => {
+                        glyph += 1;
+                        in_sequence = false;
+                    }
+                    PSF1_STARTSEQ => in_sequence = true, //combining sequences are not supported
+                    value if !in_sequence => {
+                        if let Some(c) = char::from_u32(value as u32) {
+                            unicode.entry(c).or_insert(glyph);
+                        }
+                    }
+                    _ => {}
+                }
+                if glyph == glyph_count {
+                    break;
+                }
+            }
+            Some(unicode)
+        } else {
+            None
+        };
+
+        Ok(PsfFont {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph: height,
+            width: 8,
+            height,
+            unicode,
+        })
+    }
+
+    fn parse_psf2(data: &'static [u8]) -> Result<Self, PsfError> {
+        let field = |i: usize| -> Result<usize, PsfError> {
+            let bytes = data.get(4 * i..4 * i + 4).ok_or(PsfError::Truncated)?;
+            Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+        };
+        //magic, version, header size, flags, glyph count, bytes per glyph, height, width
+        let header_size = field(2)?.max(PSF2_HEADER_SIZE);
+        let flags = field(3)? as u32;
+        let glyph_count = field(4)?;
+        let bytes_per_glyph = field(5)?;
+        let height = field(6)?;
+        let width = field(7)?;
+        //the fields can be anything up to u32::MAX, so a corrupt header must not overflow the sizes
+        let glyph_size = width.div_ceil(8).checked_mul(height).ok_or(PsfError::Truncated)?;
+        if bytes_per_glyph < glyph_size {
+            return Err(PsfError::Truncated);
+        }
+        let glyphs_end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|glyphs_size| glyphs_size.checked_add(header_size))
+            .ok_or(PsfError::Truncated)?;
+        let glyphs = data.get(header_size..glyphs_end).ok_or(PsfError::Truncated)?;
+
+        let unicode = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let mut unicode = BTreeMap::new();
+            let table = &data[glyphs_end..];
+            let mut glyph = 0;
+            let mut in_sequence = false;
+            let mut i = 0;
+            while i < table.len() && glyph < glyph_count {
+                match table[i] {
+                    PSF2_SEPARATOR => {
+                        glyph += 1;
+                        in_sequence = false;
+                        i += 1;
+                    }
+                    PSF2_STARTSEQ => {
+                        in_sequence = true; //combining sequences are not supported
+                        i += 1;
+                    }
+                    lead => {
+                        //the chars are UTF-8 encoded, so the first byte tells the length
+                        let len = match lead.leading_ones() {
+                            0 => 1,
+                            2 => 2,
+                            3 => 3,
+                            4 => 4,
+                            _ => 1, //invalid, skip the byte
+                        };
+                        let bytes = table.get(i..i + len).unwrap_or(&[]);
+                        if let Some(c) = core::str::from_utf8(bytes).ok().and_then(|s| s.chars().next()) {
+                            if !in_sequence {
+                                unicode.entry(c).or_insert(glyph);
+                            }
+                        }
+                        i += len;
+                    }
+                }
+            }
+            Some(unicode)
+        } else {
+            None
+        };
+
+        Ok(PsfFont {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+            unicode,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+}
+
+impl Font for PsfFont {
+    fn glyph(&self, c: char) -> Option<Glyph> {
+        let index = match &self.unicode {
+            Some(unicode) => *unicode.get(&c)?,
+            None => c as usize,
+        };
+        if index >= self.glyph_count {
+            return None;
+        }
+        let start = index * self.bytes_per_glyph;
+        Some(Glyph::Bitmap {
+            data: &self.glyphs[start..start + self.bytes_per_glyph],
+            width: self.width,
+            height: self.height,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PSF2 font with two 8x2 glyphs. Glyph 0 is 'A', glyph 1 is 'é' and the sequence "e" + U+0301.
+    static TWO_GLYPHS: [u8; 46] = [
+        0x72, 0xb5, 0x4a, 0x86, //magic
+        0, 0, 0, 0, //version
+        32, 0, 0, 0, //header size
+        1, 0, 0, 0, //flags: has Unicode table
+        2, 0, 0, 0, //glyph count
+        2, 0, 0, 0, //bytes per glyph
+        2, 0, 0, 0, //height
+        8, 0, 0, 0, //width
+        0xFF, 0x00, //glyph 0
+        0x80, 0x01, //glyph 1
+        b'A', 0xFF, //table of glyph 0
+        0xC3, 0xA9, 0xFE, b'e', 0xCC, 0x81, 0xFF, //table of glyph 1
+        0, //padding
+    ];
+
+    /// Just a PSF2 header, which claims u32::MAX glyphs of u32::MAX bytes each.
+    static HUGE_HEADER: [u8; 32] = [
+        0x72, 0xb5, 0x4a, 0x86, //magic
+        0, 0, 0, 0, //version
+        0xFF, 0xFF, 0xFF, 0xFF, //header size
+        0, 0, 0, 0, //flags
+        0xFF, 0xFF, 0xFF, 0xFF, //glyph count
+        0xFF, 0xFF, 0xFF, 0xFF, //bytes per glyph
+        0xFF, 0xFF, 0xFF, 0xFF, //height
+        0xFF, 0xFF, 0xFF, 0xFF, //width
+    ];
+
+    #[test_case]
+    fn psf2_unicode_table() {
+        let font = PsfFont::parse(&TWO_GLYPHS).expect("valid font");
+        assert_eq!((font.width(), font.height()), (8, 2));
+        let a = font.glyph('A').expect("A is in the table");
+        assert_eq!((a.intensity(0, 0), a.intensity(7, 0), a.intensity(0, 1)), (255, 255, 0));
+        let e_acute = font.glyph('é').expect("é is in the table");
+        assert_eq!((e_acute.intensity(0, 0), e_acute.intensity(1, 0), e_acute.intensity(7, 1)), (255, 0, 255));
+        assert!(font.glyph('e').is_none()); //only part of a sequence
+        assert!(font.glyph('B').is_none());
+        assert_eq!(PsfFont::parse(&TWO_GLYPHS[4..]).err(), Some(PsfError::BadMagic));
+        assert_eq!(PsfFont::parse(&TWO_GLYPHS[..35]).err(), Some(PsfError::Truncated));
+    }
+
+    #[test_case]
+    fn psf2_huge_header_is_truncated() {
+        assert_eq!(PsfFont::parse(&HUGE_HEADER).err(), Some(PsfError::Truncated));
+    }
+}
\ No newline at end of file
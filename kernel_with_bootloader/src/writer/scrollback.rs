@@ -0,0 +1,159 @@
+//Text history of the console. The framebuffer only holds pixels, so to be able to repaint lines
+//that scrolled off the screen we keep every char written, with its attributes, in a grid of cells.
+//The last `rows` lines of the grid are the lines on the screen, the ones before them the history.
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use super::Attributes;
+
+/// One char on the screen with the attributes it was written with.
+#[derive(Debug, Clone, Copy)]
+pub struct Cell {
+    pub c: char,
+    pub attributes: Attributes,
+}
+
+impl Cell {
+    /// An empty cell. Keeps the background of the attributes so that erased areas stay colored.
+    pub const fn blank(attributes: Attributes) -> Self {
+        Cell { c: ' ', attributes }
+    }
+}
+
+pub struct Scrollback {
+    lines: VecDeque<Vec<Cell>>,
+    columns: usize,
+    rows: usize,
+    max_history: usize,
+    view_offset: usize, //number of lines the view is scrolled back. 0 shows the live screen
+}
+
+impl Scrollback {
+    /// Creates a blank screen of columns x rows cells that keeps up to max_history lines
+    /// after they scrolled off.
+    pub fn new(columns: usize, rows: usize, max_history: usize) -> Self {
+        let mut lines = VecDeque::with_capacity(rows + max_history);
+        for _ in 0..rows {
+            lines.push_back(Self::blank_line(columns, Attributes::new()));
+        }
+        Scrollback {
+            lines,
+            columns,
+            rows,
+            max_history,
+            view_offset: 0,
+        }
+    }
+
+    fn blank_line(columns: usize, attributes: Attributes) -> Vec<Cell> {
+        vec![Cell::blank(attributes); columns]
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn max_history(&self) -> usize {
+        self.max_history
+    }
+
+    /// Returns the line of the screen in the given row. Ignores the view.
+    fn screen_line(&mut self, row: usize) -> Option<&mut Vec<Cell>> {
+        if row >= self.rows {
+            return None;
+        }
+        let index = self.lines.len() - self.rows + row;
+        self.lines.get_mut(index)
+    }
+
+    /// Records a char written on the screen. Positions outside of the screen are ignored.
+    pub fn set(&mut self, column: usize, row: usize, cell: Cell) {
+        if let Some(line) = self.screen_line(row) {
+            if let Some(target) = line.get_mut(column) {
+                *target = cell;
+            }
+        }
+    }
+
+    /// Records erasing the columns from start up to (excluding) end of the given row.
+    pub fn erase(&mut self, row: usize, start: usize, end: usize, attributes: Attributes) {
+        if let Some(line) = self.screen_line(row) {
+            let end = end.min(line.len());
+            if start < end {
+                line[start..end].fill(Cell::blank(attributes));
+            }
+        }
+    }
+
+    /// Records clearing the whole screen. The history is kept.
+    pub fn clear_screen(&mut self, attributes: Attributes) {
+        for row in 0..self.rows {
+            self.erase(row, 0, self.columns, attributes);
+        }
+    }
+
+    /// Records the screen moving up one line. The top line goes into the history, dropping the oldest
+    /// history line if it is full.
+    pub fn scroll_up(&mut self, attributes: Attributes) {
+        self.lines.push_back(Self::blank_line(self.columns, attributes));
+        if self.lines.len() > self.rows + self.max_history {
+            self.lines.pop_front();
+        }
+    }
+
+    /// Returns the line shown in the given row of the screen, taking the view into account.
+    pub fn visible_line(&self, row: usize) -> &[Cell] {
+        let index = self.lines.len() - self.rows - self.view_offset + row;
+        &self.lines[index]
+    }
+
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    /// Moves the view lines back into the history, at most up to the oldest line.
+    /// Returns true if the view changed.
+    pub fn scroll_back(&mut self, lines: usize) -> bool {
+        let history = self.lines.len() - self.rows;
+        let new_offset = (self.view_offset + lines).min(history);
+        let changed = new_offset != self.view_offset;
+        self.view_offset = new_offset;
+        changed
+    }
+
+    /// Moves the view lines forward, at most back to the live screen. Returns true if the view changed.
+    pub fn scroll_forward(&mut self, lines: usize) -> bool {
+        let new_offset = self.view_offset.saturating_sub(lines);
+        let changed = new_offset != self.view_offset;
+        self.view_offset = new_offset;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn history_keeps_scrolled_off_lines() {
+        let mut scrollback = Scrollback::new(4, 2, 3);
+        scrollback.set(0, 0, Cell { c: 'a', attributes: Attributes::new() });
+        scrollback.scroll_up(Attributes::new());
+        assert_eq!(scrollback.visible_line(0)[0].c, ' ');
+
+        //the view can not go back further than the history
+        assert!(scrollback.scroll_back(5));
+        assert_eq!(scrollback.view_offset(), 1);
+        assert_eq!(scrollback.visible_line(0)[0].c, 'a');
+        assert!(scrollback.scroll_forward(1));
+        assert!(!scrollback.scroll_forward(1));
+
+        //only max_history lines are kept
+        for _ in 0..5 {
+            scrollback.scroll_up(Attributes::new());
+        }
+        scrollback.scroll_back(10);
+        assert_eq!(scrollback.view_offset(), 3);
+        assert_eq!(scrollback.visible_line(0)[0].c, ' ');
+    }
+}
\ No newline at end of file
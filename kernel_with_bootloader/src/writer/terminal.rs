@@ -0,0 +1,77 @@
+//Virtual terminals: several consoles sharing one screen, of which one is shown at a time.
+//Each has its own cells (see scrollback), cursor, attributes and escape sequence state. The shown one
+//lives in the fields of FrameBufferWriter; the others wait here and are swapped in to be written to,
+//with drawing turned off, or to be shown (see FrameBufferWriter::switch_terminal).
+//Ref: https://en.wikipedia.org/wiki/Virtual_console
+
+use core::{fmt, mem};
+
+use super::{ansi::Parser, scrollback::Scrollback, Attributes, Color, FrameBufferWriter};
+
+/// Number of virtual terminals. Alt+F1 to Alt+F6 switch between them.
+pub const TERMINAL_COUNT: usize = 6;
+
+/// What a virtual terminal has of its own while it is not in the FrameBufferWriter.
+pub(super) struct TerminalState {
+    x_pos: usize,
+    y_pos: usize,
+    attributes: Attributes,
+    ansi_parser: Parser,
+    saved_cursor: (usize, usize, Attributes),
+    cursor_visible: bool,
+    scrollback: Option<Scrollback>,
+}
+
+impl TerminalState {
+    /// A blank terminal with the cursor at (x_pos, y_pos).
+    pub(super) fn new(x_pos: usize, y_pos: usize, scrollback: Scrollback) -> Self {
+        TerminalState {
+            x_pos,
+            y_pos,
+            attributes: Attributes::new(),
+            ansi_parser: Parser::new(),
+            saved_cursor: (x_pos, y_pos, Attributes::new()),
+            cursor_visible: true,
+            scrollback: Some(scrollback),
+        }
+    }
+
+    /// Exchanges this state with the one the writer is using.
+    pub(super) fn swap(&mut self, writer: &mut FrameBufferWriter) {
+        mem::swap(&mut self.x_pos, &mut writer.x_pos);
+        mem::swap(&mut self.y_pos, &mut writer.y_pos);
+        mem::swap(&mut self.attributes, &mut writer.attributes);
+        mem::swap(&mut self.ansi_parser, &mut writer.ansi_parser);
+        mem::swap(&mut self.saved_cursor, &mut writer.saved_cursor);
+        mem::swap(&mut self.cursor_visible, &mut writer.cursor_visible);
+        mem::swap(&mut self.scrollback, &mut writer.scrollback);
+    }
+}
+
+/// Writes to one virtual terminal, whether it is shown or not. See FrameBufferWriter::terminal.
+pub struct TerminalWriter<'a> {
+    pub(super) writer: &'a mut FrameBufferWriter,
+    pub(super) terminal: usize,
+}
+
+impl TerminalWriter<'_> {
+    /// Sets the colors of the text written to this terminal from now on.
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.writer.with_terminal(self.terminal, |writer| writer.set_color(foreground, background));
+    }
+
+    /// Returns the current (foreground, background) colors of this terminal.
+    pub fn color(&mut self) -> (Color, Color) {
+        self.writer.with_terminal(self.terminal, |writer| writer.color())
+    }
+}
+
+impl fmt::Write for TerminalWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.writer.with_terminal(self.terminal, |writer| fmt::Write::write_str(writer, s))
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        self.writer.with_terminal(self.terminal, |writer| fmt::Write::write_fmt(writer, args))
+    }
+}
\ No newline at end of file
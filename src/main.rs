@@ -1,18 +1,141 @@
+use std::process::{exit, Command};
+
+const USAGE: &str = "\
+Boots the kernel in QEMU.
+
+Usage: cargo run -- [OPTIONS] [QEMU ARGS]...
+
+Options:
+  --uefi             boot the UEFI image
+  --bios             boot the BIOS image (default)
+  --memory <SIZE>    guest memory, e.g. 256M or 1G (QEMU -m)
+  --smp <N>          number of CPUs (QEMU -smp)
+  --nographic        no display window, serial output goes to this terminal
+  --serial <FILE>    write serial output to FILE instead of this terminal
+  --gdb              start the gdbstub, see --gdb-port
+  --debug            like --gdb, but QEMU waits for the debugger before booting
+                     and a matching gdb command line is printed
+  --gdb-port <PORT>  tcp port of the gdbstub (default 1234)
+  --no-reboot        exit instead of rebooting, e.g. on a triple fault
+  -h, --help         print this help
+
+Any other argument, and everything after --, is passed to QEMU unchanged.";
+
+const DEFAULT_GDB_PORT: &str = "1234";
+
+/// Options of the runner, parsed from the command line.
+#[derive(Debug, Default)]
+struct Args {
+    uefi: bool,
+    memory: Option<String>,
+    smp: Option<String>,
+    nographic: bool,
+    serial: Option<String>,
+    gdb: bool,
+    debug: bool,
+    gdb_port: Option<String>,
+    no_reboot: bool,
+    qemu_args: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--uefi" => parsed.uefi = true,
+                "--bios" => parsed.uefi = false,
+                "--memory" => parsed.memory = Some(value_of(&arg, args.next())?),
+                "--smp" => parsed.smp = Some(value_of(&arg, args.next())?),
+                "--nographic" => parsed.nographic = true,
+                "--serial" => parsed.serial = Some(value_of(&arg, args.next())?),
+                "--gdb" => parsed.gdb = true,
+                "--debug" => parsed.debug = true,
+                "--gdb-port" => parsed.gdb_port = Some(value_of(&arg, args.next())?),
+                "--no-reboot" => parsed.no_reboot = true,
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    exit(0);
+                }
+                "--" => parsed.qemu_args.extend(args.by_ref()),
+                _ => parsed.qemu_args.push(arg), //not ours, so it is for QEMU
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+fn value_of(option: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{option} requires a value"))
+}
+
 fn main() {
     // read env variables that were set in build script
     let uefi_path = env!("UEFI_PATH");
     let bios_path = env!("BIOS_PATH");
-    
-    // choose whether to start the UEFI or BIOS image
-    let uefi = false;
+    let kernel_path = env!("KERNEL_PATH"); // the kernel ELF, which has the symbols for gdb
+
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            exit(2);
+        }
+    };
 
-    let mut cmd = std::process::Command::new("qemu-system-x86_64");
-    if uefi {
+    let mut cmd = Command::new("qemu-system-x86_64");
+    // choose whether to start the UEFI or BIOS image
+    if args.uefi {
         cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
         cmd.arg("-drive").arg(format!("format=raw,file={uefi_path}"));
     } else {
         cmd.arg("-drive").arg(format!("format=raw,file={bios_path}"));
     }
-    let mut child = cmd.spawn().unwrap();
-    child.wait().unwrap();
-}
\ No newline at end of file
+    match &args.serial {
+        Some(file) => {
+            cmd.arg("-serial").arg(format!("file:{file}"));
+        }
+        // -nographic already connects the serial port to stdio and QEMU refuses to do it twice
+        None if args.nographic => {}
+        None => {
+            //show what the kernel writes to COM1 (serial_print!/serial_println!) in this terminal
+            cmd.arg("-serial").arg("stdio");
+        }
+    }
+    if args.nographic {
+        cmd.arg("-nographic");
+    }
+    if let Some(memory) = &args.memory {
+        cmd.arg("-m").arg(memory);
+    }
+    if let Some(smp) = &args.smp {
+        cmd.arg("-smp").arg(smp);
+    }
+    if args.gdb || args.debug {
+        let port = args.gdb_port.as_deref().unwrap_or(DEFAULT_GDB_PORT);
+        cmd.arg("-gdb").arg(format!("tcp::{port}"));
+        if args.debug {
+            cmd.arg("-S"); // do not start the CPU until gdb says continue
+            // the kernel is position independent and the bootloader always relocates it, so its
+            // symbols only match once they are loaded at the offset it was relocated by
+            println!("QEMU is waiting for gdb. Connect with:");
+            println!("  gdb -ex 'target remote localhost:{port}' -ex 'add-symbol-file {kernel_path} -o <offset>'");
+            println!("where <offset> is the \"Kernel image offset\" the kernel prints on the serial port.");
+            println!("It is the same on every boot of the same kernel, so it can be taken from an earlier run.");
+        }
+    }
+    if args.no_reboot {
+        cmd.arg("-no-reboot");
+    }
+    cmd.args(&args.qemu_args);
+
+    let status = match cmd.status() {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("error: failed to start qemu-system-x86_64: {err}");
+            exit(1);
+        }
+    };
+    // forward QEMU's exit code, e.g. for scripts. Killed by a signal counts as failure
+    exit(status.code().unwrap_or(1));
+}
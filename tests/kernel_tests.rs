@@ -0,0 +1,104 @@
+// Builds the kernel in test mode and boots it in a headless QEMU.
+// The kernel runs its #[test_case] functions, prints the results over serial
+// and exits QEMU through the isa-debug-exit device. See kernel_with_bootloader/src/testing.rs
+// A second test kernel, built with the stack_overflow_test feature, overflows its stack instead,
+// as that cannot be a #[test_case] that returns.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// QEMU exits with (code << 1) | 1 for a code written to isa-debug-exit,
+// so these are QemuExitCode::Success (0x10) and QemuExitCode::Failed (0x11) of the kernel
+const QEMU_EXIT_SUCCESS: i32 = 33;
+const QEMU_EXIT_FAILED: i32 = 35;
+
+// kill QEMU if the kernel hangs, e.g. in an endless loop of a test
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+// Artifact dependencies can only give build.rs the normal kernel binary, so we build the
+// test binary ourselves with a nested `cargo test --no-run`, like bootimage used to do.
+// Done here rather than in build.rs, so that `cargo build` and `cargo run` do not pay for it.
+fn build_test_kernel(target_dir: &Path, features: &[&str]) -> PathBuf {
+    let kernel_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("kernel_with_bootloader");
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command
+        .current_dir(&kernel_dir)
+        .arg("test")
+        .arg("--no-run")
+        .arg("--bin").arg("kernel_with_bootloader")
+        .arg("--target").arg("x86_64-unknown-none")
+        .arg("--message-format=json")
+        // a separate target dir, else we could wait on the lock held by the outer cargo
+        .arg("--target-dir").arg(target_dir)
+        // these are meant for the host, not for the kernel
+        .env_remove("RUSTFLAGS")
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .stderr(Stdio::inherit());
+    if !features.is_empty() {
+        command.arg("--features").arg(features.join(","));
+    }
+    let output = command.output().expect("failed to run cargo for the kernel tests");
+    assert!(output.status.success(), "building the kernel tests failed");
+
+    // find the test executable in the json messages. Only the bin artifact has an executable path,
+    // dependencies and build scripts report "executable": null
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .find_map(|message| message["executable"].as_str().map(PathBuf::from))
+        .expect("cargo did not report the kernel test executable")
+}
+
+// Builds the test kernel with the given features, boots it and fails if it does not exit with success.
+// name keeps the builds and disk images of the test kernels apart, as the tests run in parallel
+fn run_test_kernel(name: &str, features: &[&str]) {
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let test_kernel = build_test_kernel(&out_dir.join(name), features);
+    let test_bios_path = out_dir.join(format!("{name}_bios.img"));
+    bootloader::BiosBoot::new(&test_kernel).create_disk_image(&test_bios_path).unwrap();
+
+    let mut child = Command::new("qemu-system-x86_64")
+        .arg("-drive").arg(format!("format=raw,file={}", test_bios_path.display()))
+        .arg("-device").arg("isa-debug-exit,iobase=0xf4,iosize=0x04")
+        .arg("-serial").arg("stdio")
+        .arg("-display").arg("none")
+        .arg("-no-reboot") // a triple fault should end the test instead of rebooting forever
+        .stdin(Stdio::null())
+        .spawn()
+        .expect("failed to start qemu-system-x86_64");
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if start.elapsed() > TIMEOUT {
+            child.kill().unwrap();
+            child.wait().unwrap();
+            panic!("{name} timed out after {:?}", TIMEOUT);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+
+    match status.code() {
+        Some(QEMU_EXIT_SUCCESS) => {}
+        Some(QEMU_EXIT_FAILED) => panic!("{name} failed, see the serial output above"),
+        // e.g. 0 after a triple fault, thanks to -no-reboot
+        other => panic!("QEMU exited unexpectedly with {:?} in {name}", other),
+    }
+}
+
+#[test]
+fn kernel_tests() {
+    run_test_kernel("kernel_tests", &[]);
+}
+
+// a kernel stack overflow must end in the double fault handler's panic, not in a triple fault
+#[test]
+fn stack_overflow() {
+    run_test_kernel("stack_overflow", &["stack_overflow_test"]);
+}